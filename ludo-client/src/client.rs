@@ -1,30 +1,71 @@
 use std::fs::File;
 use bevy::prelude::*;
-use std::net::UdpSocket;
+use std::net::{SocketAddr, UdpSocket};
 use std::thread;
 use std::time::{Duration, SystemTime};
 use bevy::asset::AssetContainer;
 use bevy::image;
 use bevy::render::render_resource::Texture;
 use bevy::utils::info;
+use bevy::utils::HashMap;
 use bevy::window::PrimaryWindow;
 use bevy::winit::{WinitSettings, WinitWindows};
-use bevy_renet::netcode::{ClientAuthentication, NetcodeClientTransport};
-use bevy_renet::renet::{ConnectionConfig, RenetClient};
+use bevy_renet::netcode::{ClientAuthentication, ConnectToken, NetcodeClientTransport};
+use bevy_renet::renet::{ConnectionConfig, DefaultChannel, RenetClient};
+use clap::Parser;
 use imageun::ImageReader;
 use winit::window::Icon;
-use ludo_commons::game::{LudoGameConfiguration, LudoGameProfile};
-use crate::{handler, handshake, interface};
-use crate::handler::handle_server_outcome_system;
+use ludo_commons::game::{LudoGameConfiguration, LudoGameProfile, LudoGameState, LudoGameStatistics, LudoRoomInfo};
+use ludo_commons::rules::LudoRuleSet;
+use ludo_commons::security::LudoKey;
+use ludo_commons::{security, LudoClientMessage, LudoMessage, LudoPacket};
+use ludo_commons::packets::{LudoGameOutcomeBoardPacket, LudoGameOutcomeDiceCommitmentPacket, LudoGameOutcomeDiceRevealPacket, LudoGameOutcomeDiceRollPacket, LudoGameOutcomeDisconnectPacket, LudoGameOutcomeGameResultPacket, LudoGameOutcomeGameStartPacket, LudoGameOutcomeHandshakeCallbackPacket, LudoGameOutcomeHandshakeChallengePacket, LudoGameOutcomeMatchFoundPacket, LudoGameOutcomePlayerProfilesPacket, LudoGameOutcomeProfilePacket, LudoGameOutcomeQueuedPacket, LudoGameOutcomeRoomJoinedPacket, LudoGameOutcomeRoomListPacket, LudoGameOutcomeRoomRejectedPacket, LudoGameOutcomeServerDataPacket, LudoGameOutcomeSnapshotPacket, LudoGameOutcomeStatisticsPacket, LudoGameOutcomeTurnDeadlinePacket, LudoGameOutcomeTurnPacket};
+use crate::{game, handler, handshake, interface};
+use crate::game::LudoClientMatch;
+use crate::handler::{handle_server_outcome_system, LudoOutcomePacketApp};
+use crate::handshake::LudoClientHandshakeKey;
 use crate::interface::LudoClientGameState;
 
 #[derive(Default)]
 pub struct LudoClientPlugin {
 }
 
+/// Ludo game client.
+#[derive(Parser, Debug)]
+#[command(name = "ludo-client", version)]
+pub struct LudoClientArguments {
+    /// Browse the rooms of the server instead of searching a quick play match.
+    #[arg(long)]
+    pub rooms: bool,
+    /// Players of the match to search or the room to open, the server default if missing.
+    #[arg(long)]
+    pub players: Option<u8>,
+    /// House rules of the match to search or the room to open, the server rules if missing.
+    #[arg(long)]
+    pub rules_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LudoClientLobbyMode {
+    QuickPlay,
+    RoomBrowser,
+}
+
+/* how the player wants to find a match, chosen on the command line. */
+#[derive(Resource, Debug, Clone)]
+pub struct LudoClientLobbyPreferences {
+    pub mode: LudoClientLobbyMode,
+    pub players: Option<u8>,
+    pub rules: Option<LudoRuleSet>,
+}
+
 #[derive(Event)]
 pub struct LudoClientChangeStateEvent;
 
+/* systems which need a connection to the server. */
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LudoClientConnectedSet;
+
 #[derive(Resource)]
 pub struct LudoClientConnectionStable(pub Option<bool>);
 
@@ -34,15 +75,93 @@ pub struct LudoClientConnectionInfo(pub String);
 #[derive(Resource)]
 pub struct LudoClientCachedOnlinePlayersProfiles(pub Vec<LudoGameProfile>);
 
+#[derive(Resource)]
+pub struct LudoClientCachedSpectatorsProfiles(pub Vec<LudoGameProfile>);
+
+/* statistics by unique id, none for profiles the server does not know. */
+#[derive(Resource, Default)]
+pub struct LudoClientCachedStatistics(pub HashMap<[u8; 16], Option<LudoGameStatistics>>);
+
 impl Plugin for LudoClientPlugin {
     fn build(&self, application: &mut App) {
         application
             .add_event::<LudoClientChangeStateEvent>()
             .insert_resource(LudoClientConnectionStable(None))
-            .insert_resource(LudoGameConfiguration { min_players_to_start: 0, max_players_to_start: 4 })
+            .insert_resource(LudoGameConfiguration { min_players_to_start: 0, max_players_to_start: 4, rules: LudoRuleSet::default() })
             .add_systems(PreStartup, Self::enable_system)
-            .add_systems(Update, handle_server_outcome_system.run_if(in_state(LudoClientGameState::WaitingMenu)))
-            .insert_resource(LudoClientCachedOnlinePlayersProfiles(vec![]));
+            .init_resource::<LudoClientMatch>()
+            .add_outcome_packet::<LudoGameOutcomeHandshakeCallbackPacket>()
+            .add_outcome_packet::<LudoGameOutcomeProfilePacket>()
+            .add_outcome_packet::<LudoGameOutcomeDisconnectPacket>()
+            .add_outcome_packet::<LudoGameOutcomeGameStartPacket>()
+            .add_outcome_packet::<LudoGameOutcomePlayerProfilesPacket>()
+            .add_outcome_packet::<LudoGameOutcomeServerDataPacket>()
+            .add_outcome_packet::<LudoGameOutcomeBoardPacket>()
+            .add_outcome_packet::<LudoGameOutcomeTurnPacket>()
+            .add_outcome_packet::<LudoGameOutcomeDiceCommitmentPacket>()
+            .add_outcome_packet::<LudoGameOutcomeDiceRollPacket>()
+            .add_outcome_packet::<LudoGameOutcomeDiceRevealPacket>()
+            .add_outcome_packet::<LudoGameOutcomeGameResultPacket>()
+            .add_outcome_packet::<LudoGameOutcomeHandshakeChallengePacket>()
+            .add_outcome_packet::<LudoGameOutcomeRoomListPacket>()
+            .add_outcome_packet::<LudoGameOutcomeRoomJoinedPacket>()
+            .add_outcome_packet::<LudoGameOutcomeRoomRejectedPacket>()
+            .add_outcome_packet::<LudoGameOutcomeQueuedPacket>()
+            .add_outcome_packet::<LudoGameOutcomeMatchFoundPacket>()
+            .add_outcome_packet::<LudoGameOutcomeSnapshotPacket>()
+            .add_outcome_packet::<LudoGameOutcomeTurnDeadlinePacket>()
+            .add_outcome_packet::<LudoGameOutcomeStatisticsPacket>()
+            .configure_sets(Update, LudoClientConnectedSet.run_if(in_state(LudoClientGameState::WaitingMenu).or(in_state(LudoClientGameState::GameMenu))))
+            .add_systems(Update, handle_server_outcome_system.in_set(LudoClientConnectedSet))
+            .add_systems(
+                Update,
+                (
+                    (
+                        handshake::commit_handshake_system,
+                        handler::handle_handshake_callback_packet,
+                        handler::handle_profile_packet,
+                        handler::handle_disconnect_packet,
+                        handler::handle_game_start_packet,
+                        handler::handle_player_profiles_packet,
+                        handler::handle_server_data_packet,
+                        handler::handle_board_packet,
+                        handler::handle_turn_packet,
+                        handler::handle_dice_commitment_packet
+                    ),
+                    (
+                        handler::handle_dice_roll_packet,
+                        handler::handle_dice_reveal_packet,
+                        handler::handle_game_result_packet,
+                        handler::handle_room_list_packet,
+                        handler::handle_room_joined_packet,
+                        handler::handle_room_rejected_packet,
+                        handler::handle_queued_packet,
+                        handler::handle_match_found_packet,
+                        handler::handle_snapshot_packet,
+                        handler::handle_turn_deadline_packet,
+                        handler::handle_statistics_packet
+                    )
+                ).after(handle_server_outcome_system).in_set(LudoClientConnectedSet)
+            )
+            .add_systems(Update, game::client_game_input_system.run_if(in_state(LudoClientGameState::GameMenu)))
+            .insert_resource(LudoClientCachedOnlinePlayersProfiles(vec![]))
+            .insert_resource(LudoClientCachedSpectatorsProfiles(vec![]))
+            .init_resource::<LudoClientCachedStatistics>();
+    }
+}
+
+impl LudoClientLobbyPreferences {
+    pub fn from_arguments(arguments: &LudoClientArguments) -> anyhow::Result<Self> {
+        let rules = arguments.rules_file.as_deref().map(LudoRuleSet::load_from_file).transpose()?;
+        let mode = if arguments.rooms { LudoClientLobbyMode::RoomBrowser } else { LudoClientLobbyMode::QuickPlay };
+        Ok(Self { mode, players: arguments.players, rules })
+    }
+
+    /* a waiting room with a free seat, sized and ruled as the player asked for. */
+    pub fn accepts(&self, room: &LudoRoomInfo) -> bool {
+        room.state == LudoGameState::Waiting && room.players < room.max_players
+            && self.players.is_none_or(|players| room.max_players == players)
+            && self.rules.as_ref().is_none_or(|rules| room.rules == *rules)
     }
 }
 
@@ -65,22 +184,19 @@ impl LudoClientPlugin {
     ) {
         connection_information.iter().for_each(|connection_info| {
             let client = RenetClient::new(ConnectionConfig::default());
-            if let Ok(address) = connection_info.0.parse() {
+            if let (Ok(address), Some(connect_token), Some(handshake_key)) = (connection_info.0.parse::<SocketAddr>(), Self::load_connect_token(), Self::load_handshake_key()) {
+                if !connect_token.server_addresses.contains(&Some(address)) {
+                    warn!("connect token was not issued for {}, connecting to its own server addresses.", address);
+                }
                 let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-                let authentication = ClientAuthentication::Unsecure {
-                    server_addr: address,
-                    client_id: current_time.as_millis() as u64,
-                    user_data: None,
-                    protocol_id: 0,
-                };
+                let authentication = ClientAuthentication::Secure { connect_token };
                 let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
                 let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
                 commands.insert_resource(client);
                 commands.insert_resource(transport);
+                commands.insert_resource(LudoClientHandshakeKey(handshake_key));
                 info!("Starting Ludo client tasks on: {}", address);
                 commands.insert_resource(State::new(LudoClientGameState::WaitingMenu));
-                let system_id_handshake = commands.register_system(handshake::commit_handshake_system);
-                commands.run_system(system_id_handshake);
                 let _ = connection_stable.0.insert(true);
                 let system_id_interface = commands.register_system(interface::LudoClientUserInterfacePlugin::client_change_state_listener);
                 commands.run_system(system_id_interface);
@@ -92,4 +208,31 @@ impl LudoClientPlugin {
         });
     }
 
+    /* distributed by the server operator next to the connect token. */
+    fn load_handshake_key() -> Option<LudoKey> {
+        security::load_key(security::LUDO_HANDSHAKE_KEY_FILE).map_err(|error| error!("unable to load {}: {}", security::LUDO_HANDSHAKE_KEY_FILE, error)).ok()
+    }
+
+    /* the token is minted by `ludo-token-issuer` for the profile of this client. */
+    fn load_connect_token() -> Option<ConnectToken> {
+        let mut file = match File::open(security::LUDO_CONNECT_TOKEN_FILE) {
+            Ok(file) => file,
+            Err(error) => {
+                error!("unable to open {}: {}", security::LUDO_CONNECT_TOKEN_FILE, error);
+                return None;
+            }
+        };
+        match ConnectToken::read(&mut file) {
+            Ok(connect_token) => Some(connect_token),
+            Err(error) => {
+                error!("invalid connect token: {}", error);
+                None
+            }
+        }
+    }
+}
+
+pub fn send_packet<T>(client: &mut RenetClient, packet: T) where T: LudoPacket + Into<LudoClientMessage> {
+    let packet = packet.into().encode().expect("unable to encode packet");
+    client.send_message(DefaultChannel::ReliableOrdered, packet);
 }
\ No newline at end of file
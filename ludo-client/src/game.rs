@@ -1,10 +1,34 @@
+use std::time::Instant;
 use bevy::log::info;
-use bevy::prelude::{Camera2d, Camera3dBundle, Commands, Entity, MonitorSelection, Query, Window, WindowPosition, With};
+use bevy::prelude::{ButtonInput, Camera2d, Camera3dBundle, Commands, Entity, KeyCode, MonitorSelection, Query, Res, ResMut, Resource, Window, WindowPosition, With};
 use bevy::ui::State;
 use bevy::utils::default;
 use bevy::window::{WindowMode, WindowResolution};
+use bevy_renet::renet::RenetClient;
+use ludo_commons::packets::{LudoGameIncomeMovePacket, LudoGameIncomeRollPacket, LudoGameOutcomeTurnPacket};
+use ludo_commons::dice::{LudoDiceCommitment, LudoDiceRoll};
+use ludo_commons::rules::{LudoBoardState, LudoColor};
+use crate::client;
 use crate::interface::{LudoClientGameState, LudoInterfaceMenuComponent};
 
+/* the latest match state as broadcast by the server. */
+#[derive(Resource, Default)]
+pub struct LudoClientMatch {
+    pub color: Option<LudoColor>,
+    pub board: Option<LudoBoardState>,
+    pub turn: Option<LudoGameOutcomeTurnPacket>,
+    pub dice_commitment: Option<LudoDiceCommitment>,
+    pub rolls: Vec<LudoDiceRoll>,
+    /* when the server acts for the active colour. */
+    pub turn_deadline: Option<Instant>,
+}
+
+impl LudoClientMatch {
+    pub fn is_my_turn(&self) -> bool {
+        self.color.is_some() && self.turn.as_ref().map(|turn| turn.color) == self.color
+    }
+}
+
 pub fn client_load_game(mut commands: Commands, mut window_query: Query<&mut Window>) {
     info!("loading game!");
     commands.insert_resource(bevy::prelude::State::new(LudoClientGameState::GameMenu));
@@ -23,4 +47,23 @@ fn client_despawn_interface(mut commands: Commands, camera_component: Query<Enti
 
 fn client_despawn_camera(mut commands: Commands, interface_menu: Query<Entity, With<Camera2d>>) {
     commands.get_entity(interface_menu.get_single().unwrap()).unwrap().clear().despawn();
+}
+
+/* space asks the server to roll, the number keys pick the token to move. */
+pub fn client_game_input_system(keys: Res<ButtonInput<KeyCode>>, ludo_match: Res<LudoClientMatch>, mut client: ResMut<RenetClient>) {
+    if !ludo_match.is_my_turn() {
+        return;
+    }
+    let Some(turn) = &ludo_match.turn else { return };
+    if turn.roll.is_none() {
+        if keys.just_pressed(KeyCode::Space) {
+            client::send_packet(&mut client, LudoGameIncomeRollPacket::new());
+        }
+    } else {
+        for (token, key) in [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4].iter().enumerate() {
+            if keys.just_pressed(*key) && turn.moves.iter().any(|ludo_move| ludo_move.token == token) {
+                client::send_packet(&mut client, LudoGameIncomeMovePacket::new(token));
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -1,47 +1,234 @@
+use std::time::{Duration, Instant};
 use bevy::log::{info, warn};
-use bevy::prelude::{error, Commands, Query, Res, ResMut};
+use bevy::prelude::{error, App, Commands, Event, EventReader, Query, Res, ResMut, State};
 use bevy_renet::renet::{DefaultChannel, RenetClient};
-use ludo_commons::game::{LudoGameConfiguration, LudoGameProfile};
-use ludo_commons::LudoPacket;
-use ludo_commons::packets::{LudoGameIncomeProfilePacket, LudoGameOutcomeDisconnectPacket, LudoGameOutcomeGameStartPacket, LudoGameOutcomeHandshakeCallbackPacket, LudoGameOutcomePlayerProfilesPacket, LudoGameOutcomeProfilePacket, LudoGameOutcomeServerDataPacket};
-use crate::client::LudoClientCachedOnlinePlayersProfiles;
+use ludo_commons::game::{LudoGameConfiguration, LudoGameProfile, LudoGameProfileKey};
+use ludo_commons::{dice, security, LudoMessage, LudoPacket, LudoPacketDispatcher, LudoServerMessage};
+use ludo_commons::packets::{LudoGameIncomeCreateRoomPacket, LudoGameIncomeFindMatchPacket, LudoGameIncomeJoinRoomPacket, LudoGameIncomeListRoomsPacket, LudoGameIncomeProfilePacket, LudoGameIncomeStatisticsPacket, LudoGameOutcomeBoardPacket, LudoGameOutcomeDiceCommitmentPacket, LudoGameOutcomeDiceRevealPacket, LudoGameOutcomeDiceRollPacket, LudoGameOutcomeDisconnectPacket, LudoGameOutcomeGameResultPacket, LudoGameOutcomeGameStartPacket, LudoGameOutcomeHandshakeCallbackPacket, LudoGameOutcomeMatchFoundPacket, LudoGameOutcomePlayerProfilesPacket, LudoGameOutcomeProfilePacket, LudoGameOutcomeQueuedPacket, LudoGameOutcomeRoomJoinedPacket, LudoGameOutcomeRoomListPacket, LudoGameOutcomeRoomRejectedPacket, LudoGameOutcomeServerDataPacket, LudoGameOutcomeSnapshotPacket, LudoGameOutcomeStatisticsPacket, LudoGameOutcomeTurnDeadlinePacket, LudoGameOutcomeTurnPacket};
+use crate::client::{self, LudoClientCachedOnlinePlayersProfiles, LudoClientCachedSpectatorsProfiles, LudoClientCachedStatistics, LudoClientLobbyMode, LudoClientLobbyPreferences};
 use crate::{game, interface};
+use crate::game::LudoClientMatch;
+use crate::handshake::LudoClientChallenge;
+use crate::interface::LudoClientGameState;
 
-pub fn handle_server_outcome_system(mut commands: Commands, mut client: ResMut<RenetClient>, profile: Query<&LudoGameProfile>, mut cached_profiles: ResMut<LudoClientCachedOnlinePlayersProfiles>) {
-    let outcome_message = client.receive_message(DefaultChannel::ReliableOrdered);
-    if let Some(outcome_message) = outcome_message {
-        if let Ok(_handshake_packet_outcome) = LudoGameOutcomeHandshakeCallbackPacket::make_packet::<LudoGameOutcomeHandshakeCallbackPacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-            info!("handshake successfully received!");
-            info!("requested profile data...");
-            let packet = LudoGameIncomeProfilePacket::new(profile.get_single().expect("no profile provided by the system!").clone());
-            client.send_message(DefaultChannel::ReliableOrdered, packet.into_string::<LudoGameIncomeProfilePacket>().expect("unable to parse #(LudoGameIncomeProfilePacket) to raw!"));
-        } else {
-            if let Ok(profile_packet_outcome) = LudoGameOutcomeProfilePacket::make_packet::<LudoGameOutcomeProfilePacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-                info!("response successfully received!");
-                info!("data: {:?}", profile_packet_outcome);
-            } else {
-                if let Ok(disconnect_packet) = LudoGameOutcomeDisconnectPacket::make_packet::<LudoGameOutcomeDisconnectPacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-                    error!("disconnection received: {}!", disconnect_packet.reason);
-                } else {
-                    if let Ok(_game_start_packet) = LudoGameOutcomeGameStartPacket::make_packet::<LudoGameOutcomeGameStartPacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-                        let system_id = commands.register_system(game::client_load_game);
-                        commands.run_system(system_id);
-                    } else {
-                        if let Ok(communication_profiles_packet) = LudoGameOutcomePlayerProfilesPacket::make_packet::<LudoGameOutcomePlayerProfilesPacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-                            cached_profiles.0 = communication_profiles_packet.list;
-                        } else {
-                            if let Ok(data_packet) = LudoGameOutcomeServerDataPacket::make_packet::<LudoGameOutcomeServerDataPacket>(String::from_utf8_lossy(&outcome_message).to_string()) {
-                                commands.insert_resource(LudoGameConfiguration {
-                                    min_players_to_start: data_packet.min_players as u8,
-                                    max_players_to_start: 4,
-                                });
-                                let system_id = commands.register_system(interface::LudoClientUserInterfacePlugin::client_load_minimum_players_system);
-                                commands.run_system(system_id);
-                            }
-                        }
-                    }
-                }
+/* one typed event per outgoing server packet type, registered with `LudoOutcomePacketApp::add_outcome_packet`. */
+#[derive(Event)]
+pub struct LudoOutcomePacketEvent<T>(pub T) where T: LudoPacket + Send + Sync + 'static;
+
+pub trait LudoOutcomePacketApp {
+    fn add_outcome_packet<T>(&mut self) -> &mut Self where T: LudoPacket + Send + Sync + 'static;
+}
+
+impl LudoOutcomePacketApp for App {
+    fn add_outcome_packet<T>(&mut self) -> &mut Self where T: LudoPacket + Send + Sync + 'static {
+        self.add_event::<LudoOutcomePacketEvent<T>>()
+    }
+}
+
+struct LudoOutcomeDispatcher<'a, 'w, 's> {
+    commands: &'a mut Commands<'w, 's>,
+}
+
+impl LudoPacketDispatcher for LudoOutcomeDispatcher<'_, '_, '_> {
+    fn dispatch<T>(&mut self, packet: T) where T: LudoPacket + Send + Sync + 'static {
+        self.commands.send_event(LudoOutcomePacketEvent(packet));
+    }
+}
+
+/* decodes every message of the server once and hands it to the event of its packet type. */
+pub fn handle_server_outcome_system(mut commands: Commands, mut client: ResMut<RenetClient>) {
+    while let Some(outcome_message) = client.receive_message(DefaultChannel::ReliableOrdered) {
+        match LudoServerMessage::decode(&outcome_message) {
+            Ok(outcome_message) => outcome_message.dispatch(&mut LudoOutcomeDispatcher { commands: &mut commands }),
+            Err(error) => warn!("invalid packet from the server: {}", error),
+        }
+    }
+}
+
+/* sends the signed profile, then searches a quick play match or asks for the rooms as the player chose. */
+pub fn handle_handshake_callback_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeHandshakeCallbackPacket>>, mut client: ResMut<RenetClient>, profile: Query<(&LudoGameProfile, &LudoGameProfileKey)>, challenge: Res<LudoClientChallenge>, preferences: Res<LudoClientLobbyPreferences>) {
+    for _handshake_packet_outcome in events.read() {
+        info!("handshake successfully received!");
+        info!("requested profile data...");
+        let (profile, profile_key) = profile.get_single().expect("no profile provided by the system!");
+        let signature = security::sign_profile(&profile_key.0, &challenge.0, &profile.unique_id);
+        let packet = LudoGameIncomeProfilePacket::new(profile.clone(), signature);
+        client::send_packet(&mut client, packet);
+        match preferences.mode {
+            LudoClientLobbyMode::QuickPlay => client::send_packet(&mut client, LudoGameIncomeFindMatchPacket::new(preferences.players, preferences.rules.clone())),
+            LudoClientLobbyMode::RoomBrowser => client::send_packet(&mut client, LudoGameIncomeListRoomsPacket::new()),
+        }
+    }
+}
+
+/* joins the first room which still waits for players, otherwise opens a new one with the preferences of the player. */
+pub fn handle_room_list_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeRoomListPacket>>, mut client: ResMut<RenetClient>, profile: Query<&LudoGameProfile>, preferences: Res<LudoClientLobbyPreferences>) {
+    for LudoOutcomePacketEvent(room_list_packet) in events.read() {
+        info!("{} rooms on the server.", room_list_packet.rooms.len());
+        match room_list_packet.rooms.iter().find(|room| preferences.accepts(room)) {
+            Some(room) => client::send_packet(&mut client, LudoGameIncomeJoinRoomPacket::new(room.id)),
+            None => {
+                let name = profile.get_single().map(|profile| profile.nickname.clone()).unwrap_or("ludo".to_string());
+                client::send_packet(&mut client, LudoGameIncomeCreateRoomPacket::new(name, None, preferences.players, preferences.rules.clone(), None));
             }
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn handle_room_joined_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeRoomJoinedPacket>>) {
+    for LudoOutcomePacketEvent(room_joined_packet) in events.read() {
+        let room = &room_joined_packet.room;
+        info!("joined room {} \"{}\" ({}/{} players).", room.id, room.name, room.players, room.max_players);
+    }
+}
+
+pub fn handle_queued_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeQueuedPacket>>) {
+    for LudoOutcomePacketEvent(queued_packet) in events.read() {
+        info!("searching a match of {} players, {} clients in the queue...", queued_packet.players, queued_packet.queued);
+    }
+}
+
+pub fn handle_match_found_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeMatchFoundPacket>>) {
+    for LudoOutcomePacketEvent(match_found_packet) in events.read() {
+        info!("match found in room {} with {}!", match_found_packet.room.id, match_found_packet.players.join(", "));
+    }
+}
+
+pub fn handle_room_rejected_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeRoomRejectedPacket>>) {
+    for LudoOutcomePacketEvent(room_rejected_packet) in events.read() {
+        error!("room request rejected: {}!", room_rejected_packet.reason);
+    }
+}
+
+pub fn handle_profile_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeProfilePacket>>) {
+    for LudoOutcomePacketEvent(profile_packet_outcome) in events.read() {
+        info!("response successfully received!");
+        info!("data: {:?}", profile_packet_outcome);
+    }
+}
+
+pub fn handle_disconnect_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeDisconnectPacket>>) {
+    for LudoOutcomePacketEvent(disconnect_packet) in events.read() {
+        error!("disconnection received: {}!", disconnect_packet.reason);
+    }
+}
+
+pub fn handle_game_start_packet(mut commands: Commands, mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeGameStartPacket>>, mut ludo_match: ResMut<LudoClientMatch>, state: Res<State<LudoClientGameState>>) {
+    for LudoOutcomePacketEvent(game_start_packet) in events.read() {
+        info!("playing as {:?}!", game_start_packet.color);
+        ludo_match.color = Some(game_start_packet.color);
+        if *state.get() != LudoClientGameState::GameMenu {
+            let system_id = commands.register_system(game::client_load_game);
+            commands.run_system(system_id);
+        }
+    }
+}
+
+/* resumes a running match after a reconnect or shows the match a spectator joined. */
+pub fn handle_snapshot_packet(mut commands: Commands, mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeSnapshotPacket>>, mut ludo_match: ResMut<LudoClientMatch>, state: Res<State<LudoClientGameState>>) {
+    for LudoOutcomePacketEvent(snapshot_packet) in events.read() {
+        match snapshot_packet.color {
+            Some(color) => info!("resumed room {} as {:?} after {} rolls!", snapshot_packet.room.id, color, snapshot_packet.rolls.len()),
+            None => info!("spectating room {} after {} rolls!", snapshot_packet.room.id, snapshot_packet.rolls.len()),
+        }
+        ludo_match.color = snapshot_packet.color;
+        ludo_match.board = Some(snapshot_packet.board.clone());
+        ludo_match.turn = Some(snapshot_packet.turn.clone());
+        ludo_match.dice_commitment = snapshot_packet.dice_commitment;
+        ludo_match.rolls = snapshot_packet.rolls.clone();
+        if *state.get() != LudoClientGameState::GameMenu {
+            let system_id = commands.register_system(game::client_load_game);
+            commands.run_system(system_id);
+        }
+    }
+}
+
+/* caches the profiles of the room and asks for the statistics of every player seen for the first time. */
+pub fn handle_player_profiles_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomePlayerProfilesPacket>>, mut client: ResMut<RenetClient>, mut cached_profiles: ResMut<LudoClientCachedOnlinePlayersProfiles>, mut cached_spectators: ResMut<LudoClientCachedSpectatorsProfiles>, cached_statistics: Res<LudoClientCachedStatistics>) {
+    for LudoOutcomePacketEvent(communication_profiles_packet) in events.read() {
+        cached_profiles.0 = communication_profiles_packet.list.clone();
+        cached_spectators.0 = communication_profiles_packet.spectators.clone();
+        cached_profiles.0.iter().filter(|profile| !cached_statistics.0.contains_key(&profile.unique_id)).for_each(|profile| {
+            client::send_packet(&mut client, LudoGameIncomeStatisticsPacket::new(Some(profile.unique_id)));
+        });
+    }
+}
+
+pub fn handle_statistics_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeStatisticsPacket>>, mut cached_statistics: ResMut<LudoClientCachedStatistics>) {
+    for LudoOutcomePacketEvent(statistics_packet) in events.read() {
+        if let Some(statistics) = &statistics_packet.statistics {
+            info!("statistics of {:?}: {} games, {} wins, {} losses, average game {}s.", statistics_packet.unique_id, statistics.games, statistics.wins, statistics.losses, statistics.average_game_secs());
+        }
+        cached_statistics.0.insert(statistics_packet.unique_id, statistics_packet.statistics.clone());
+    }
+}
+
+pub fn handle_server_data_packet(mut commands: Commands, mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeServerDataPacket>>) {
+    for LudoOutcomePacketEvent(data_packet) in events.read() {
+        commands.insert_resource(LudoGameConfiguration {
+            min_players_to_start: data_packet.min_players as u8,
+            max_players_to_start: 4,
+            rules: data_packet.rules.clone(),
+        });
+        let system_id = commands.register_system(interface::LudoClientUserInterfacePlugin::client_load_minimum_players_system);
+        commands.run_system(system_id);
+    }
+}
+
+pub fn handle_board_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeBoardPacket>>, mut ludo_match: ResMut<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(board_packet) in events.read() {
+        ludo_match.board = Some(board_packet.board.clone());
+    }
+}
+
+pub fn handle_turn_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeTurnPacket>>, mut ludo_match: ResMut<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(turn_packet) in events.read() {
+        info!("turn of {:?}, roll: {:?}", turn_packet.color, turn_packet.roll);
+        ludo_match.turn = Some(turn_packet.clone());
+    }
+}
+
+pub fn handle_turn_deadline_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeTurnDeadlinePacket>>, mut ludo_match: ResMut<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(deadline_packet) in events.read() {
+        ludo_match.turn_deadline = Some(Instant::now() + Duration::from_millis(deadline_packet.remaining_ms));
+        if ludo_match.color == Some(deadline_packet.color) {
+            info!("{}s left for your turn!", deadline_packet.remaining_ms / 1000);
+        }
+    }
+}
+
+pub fn handle_dice_roll_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeDiceRollPacket>>, mut ludo_match: ResMut<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(roll_packet) in events.read() {
+        info!("{:?} rolled a {}!", roll_packet.color, roll_packet.roll.value);
+        ludo_match.rolls.push(roll_packet.roll);
+    }
+}
+
+pub fn handle_dice_commitment_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeDiceCommitmentPacket>>, mut ludo_match: ResMut<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(commitment_packet) in events.read() {
+        ludo_match.dice_commitment = Some(commitment_packet.commitment);
+        ludo_match.rolls.clear();
+    }
+}
+
+pub fn handle_dice_reveal_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeDiceRevealPacket>>, ludo_match: Res<LudoClientMatch>) {
+    for LudoOutcomePacketEvent(reveal_packet) in events.read() {
+        match ludo_match.dice_commitment {
+            Some(commitment) if dice::verify(reveal_packet.seed, commitment, &ludo_match.rolls) => info!("dice verified: all {} rolls match the revealed seed!", ludo_match.rolls.len()),
+            Some(_) => error!("dice verification failed: the revealed seed does not match the rolls of this match!"),
+            None => warn!("dice seed revealed without a commitment!"),
+        }
+    }
+}
+
+pub fn handle_game_result_packet(mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeGameResultPacket>>, mut ludo_match: ResMut<LudoClientMatch>, mut cached_statistics: ResMut<LudoClientCachedStatistics>) {
+    for LudoOutcomePacketEvent(result_packet) in events.read() {
+        result_packet.placements.iter().for_each(|placement| {
+            info!("place {}: {:?} {} (+{} points)", placement.place, placement.color, placement.nickname.clone().unwrap_or("unknown".to_string()), placement.points);
+        });
+        ludo_match.turn = None;
+        ludo_match.turn_deadline = None;
+        /* the match changed the statistics of everybody in it, they are asked for again. */
+        cached_statistics.0.clear();
+    }
+}
@@ -1,14 +1,26 @@
 use bevy::log::info;
-use bevy::prelude::{Component, Event, EventReader, ResMut};
-use bevy_renet::renet::{DefaultChannel, RenetClient};
-use ludo_commons::{security, LudoPacket};
-use ludo_commons::packets::LudoGameIncomeHandshakePacket;
+use bevy::prelude::{Commands, EventReader, Res, ResMut, Resource};
+use bevy_renet::renet::RenetClient;
+use ludo_commons::{protocol, security};
+use ludo_commons::packets::{LudoGameIncomeHandshakePacket, LudoGameOutcomeHandshakeChallengePacket};
+use ludo_commons::security::{LudoKey, LudoNonce};
+use crate::client;
+use crate::handler::LudoOutcomePacketEvent;
 
+/* secret shared with the server, never sent over the wire. */
+#[derive(Resource)]
+pub struct LudoClientHandshakeKey(pub LudoKey);
 
-pub fn commit_handshake_system(mut client: ResMut<RenetClient>) {
-    let handshake_packet = LudoGameIncomeHandshakePacket::new(security::SECRET_KEY);
-    if let Ok(handshake_packet) = handshake_packet.into_string::<LudoGameIncomeHandshakePacket>() {
-        client.send_message(DefaultChannel::ReliableOrdered, handshake_packet);
+/* nonce of the current session, the profile is signed together with it. */
+#[derive(Resource)]
+pub struct LudoClientChallenge(pub LudoNonce);
+
+pub fn commit_handshake_system(mut commands: Commands, mut events: EventReader<LudoOutcomePacketEvent<LudoGameOutcomeHandshakeChallengePacket>>, mut client: ResMut<RenetClient>, handshake_key: Res<LudoClientHandshakeKey>) {
+    for LudoOutcomePacketEvent(challenge_packet) in events.read() {
+        commands.insert_resource(LudoClientChallenge(challenge_packet.nonce));
+        let response = security::handshake_response(&handshake_key.0, &challenge_packet.nonce);
+        let handshake_packet = LudoGameIncomeHandshakePacket::new(response, protocol::LUDO_PROTOCOL_VERSION, env!("CARGO_PKG_VERSION").to_string());
+        client::send_packet(&mut client, handshake_packet);
         info!("handshake packet sent!");
     }
-}
\ No newline at end of file
+}
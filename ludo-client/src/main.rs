@@ -4,7 +4,7 @@ mod handler;
 mod interface;
 mod game;
 
-use crate::client::LudoClientPlugin;
+use crate::client::{LudoClientArguments, LudoClientLobbyPreferences, LudoClientPlugin};
 use crate::interface::LudoClientUserInterfacePlugin;
 use bevy::log::tracing_subscriber;
 use bevy::prelude::*;
@@ -12,10 +12,20 @@ use bevy::window::{WindowLevel, WindowTheme};
 use bevy_renet::netcode::NetcodeClientPlugin;
 use bevy_renet::RenetClientPlugin;
 use bevy_simple_text_input::TextInputPlugin;
+use clap::Parser;
 
 fn main() {
     tracing_subscriber::fmt().compact().with_ansi(true).init();
+    let arguments = LudoClientArguments::parse();
+    let preferences = match LudoClientLobbyPreferences::from_arguments(&arguments) {
+        Ok(preferences) => preferences,
+        Err(error) => {
+            error!("invalid client arguments: {}", error);
+            std::process::exit(1);
+        }
+    };
     App::new()
+        .insert_resource(preferences)
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "ludo game".to_string(),
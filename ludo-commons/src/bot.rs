@@ -0,0 +1,57 @@
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use crate::rules::{LudoBoardState, LudoColor, LudoMove, LudoMoveKind, LudoTokenPosition, LudoTurnOptions};
+
+/* Move selection of bot players. Like the rules it only looks at the board, the server decides when a bot acts. */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LudoBotDifficulty {
+    /* any legal move. */
+    Random,
+    /* captures first, then whatever brings a token furthest. */
+    #[default]
+    Greedy,
+    /* the greedy choice, weighed against the tokens the opponents could capture on their next roll. */
+    Lookahead,
+}
+
+/* weight of one capture threat of the opponents after a move. */
+const LUDO_BOT_THREAT_WEIGHT: i32 = 12;
+
+pub fn choose_move(board: &LudoBoardState, moves: &[LudoMove], difficulty: LudoBotDifficulty) -> Option<LudoMove> {
+    match difficulty {
+        LudoBotDifficulty::Random => moves.choose(&mut rand::thread_rng()).cloned(),
+        LudoBotDifficulty::Greedy => moves.iter().max_by_key(|ludo_move| greedy_score(ludo_move)).cloned(),
+        LudoBotDifficulty::Lookahead => moves.iter().max_by_key(|ludo_move| lookahead_score(board, ludo_move)).cloned(),
+    }
+}
+
+fn greedy_score(ludo_move: &LudoMove) -> i32 {
+    let kind = match ludo_move.kind {
+        LudoMoveKind::Finish => 80,
+        LudoMoveKind::EnterHomeColumn => 40,
+        LudoMoveKind::LeaveYard => 30,
+        LudoMoveKind::Advance => 0,
+    };
+    ludo_move.captures.len() as i32 * 100 + kind + ludo_move.to.progress().unwrap_or(0) as i32
+}
+
+fn lookahead_score(board: &LudoBoardState, ludo_move: &LudoMove) -> i32 {
+    let before = threats(board, ludo_move.player);
+    let after = threats(&board.apply(ludo_move), ludo_move.player);
+    greedy_score(ludo_move) + (before - after) * LUDO_BOT_THREAT_WEIGHT
+}
+
+/* every opponent move of the next roll which would capture a token of the colour, tokens on the track only. */
+fn threats(board: &LudoBoardState, color: LudoColor) -> i32 {
+    let exposed = board.player(color).is_some_and(|player| player.tokens.iter().any(|token| matches!(token, LudoTokenPosition::Track(_))));
+    if !exposed {
+        return 0;
+    }
+    board.players.iter().filter(|player| player.color != color && !player.has_finished()).map(|player| {
+        (1..=6).map(|roll| match board.legal_moves(player.color, roll) {
+            LudoTurnOptions::Moves(moves) => moves.iter().filter(|ludo_move| ludo_move.captures.iter().any(|(captured, _)| *captured == color)).count() as i32,
+            LudoTurnOptions::Pass => 0,
+        }).sum::<i32>()
+    }).sum()
+}
@@ -0,0 +1,75 @@
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::OsRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/* Dice rolled by the server only. Every match gets its own seeded generator, so a published seed lets anybody replay the exact rolls. */
+
+pub type LudoDiceSeed = [u8; 32];
+pub type LudoDiceCommitment = [u8; 32];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LudoDiceRoll {
+    /* position of the roll inside the match, starting at 0. */
+    pub sequence: u32,
+    pub value: u8,
+}
+
+pub struct LudoDice {
+    seed: LudoDiceSeed,
+    generator: ChaCha20Rng,
+    rolls: u32,
+}
+
+impl LudoDice {
+    pub fn new(seed: LudoDiceSeed) -> Self {
+        Self { seed, generator: ChaCha20Rng::from_seed(seed), rolls: 0 }
+    }
+
+    pub fn from_entropy() -> Self {
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        Self::new(seed)
+    }
+
+    pub fn roll(&mut self) -> LudoDiceRoll {
+        let roll = LudoDiceRoll { sequence: self.rolls, value: self.generator.gen_range(1..=6) };
+        self.rolls += 1;
+        roll
+    }
+
+    pub fn rolls(&self) -> u32 {
+        self.rolls
+    }
+
+    /* published at match start, before a single roll is known. */
+    pub fn commitment(&self) -> LudoDiceCommitment {
+        commitment_of(&self.seed)
+    }
+
+    /* revealed at match end, only then can the players verify the rolls. */
+    pub fn seed(&self) -> LudoDiceSeed {
+        self.seed
+    }
+}
+
+pub fn commitment_of(seed: &LudoDiceSeed) -> LudoDiceCommitment {
+    Sha256::digest(seed).into()
+}
+
+/* recomputes the first rolls of a revealed seed. */
+pub fn replay(seed: LudoDiceSeed, count: u32) -> Vec<LudoDiceRoll> {
+    let mut dice = LudoDice::new(seed);
+    (0..count).map(|_| dice.roll()).collect()
+}
+
+/* checks a revealed seed against the published commitment and every roll seen during the match. */
+pub fn verify(seed: LudoDiceSeed, commitment: LudoDiceCommitment, rolls: &[LudoDiceRoll]) -> bool {
+    if commitment_of(&seed) != commitment {
+        return false;
+    }
+    let count = rolls.iter().map(|roll| roll.sequence + 1).max().unwrap_or(0);
+    let replayed = replay(seed, count);
+    rolls.iter().all(|roll| replayed.get(roll.sequence as usize) == Some(roll))
+}
@@ -1,11 +1,15 @@
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::collections::HashMap;
 use bevy::prelude::{Component, Resource, States};
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde::de::IgnoredAny;
 use derive_new::new;
 use uuid::Uuid;
+use crate::bot::LudoBotDifficulty;
+use crate::rules::{LudoColor, LudoRuleSet};
+use crate::{security, storage};
+use crate::security::LudoKey;
+use crate::schema::LudoSchema;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Resource)]
 pub struct LudoGameObject {
@@ -25,85 +29,267 @@ pub struct LudoGameProfile {
     pub unique_id: [u8; 16],
     pub nickname: String,
     pub age: u8,
+    /* public half of the profile keypair, proves the ownership of the unique id. */
+    #[serde(default)]
+    pub public_key: [u8; 32],
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Component)]
+/* secret half of the profile keypair, it never leaves the machine of the player. */
+#[derive(Debug, Clone, Component)]
+pub struct LudoGameProfileKey(pub LudoKey);
+
+/* the own profile as stored on disk, only here the secret key is serialized. */
+#[derive(Serialize, Deserialize)]
+struct LudoGameLocalProfile {
+    #[serde(flatten)]
+    profile: LudoGameProfile,
+    #[serde(default)]
+    secret_key: Option<LudoKey>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Component)]
 pub struct LudoGameProfileData {
     pub unique_id: [u8; 16],
     pub points: u128,
+    /* key the profile was registered with, profiles of older servers are claimed by their next login. */
+    #[serde(default)]
+    pub public_key: Option<[u8; 32]>,
+    #[serde(default)]
+    pub statistics: LudoGameStatistics,
+}
+
+/* matches kept in the history of a profile. */
+pub const LUDO_MATCH_HISTORY_LENGTH: usize = 10;
+
+/* lifetime statistics of a profile, only the server changes them. */
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LudoGameStatistics {
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    /* how often the profile finished on each place, first place first. */
+    pub placements: Vec<u32>,
+    pub captures: u32,
+    pub tokens_lost: u32,
+    pub sixes: u32,
+    pub total_game_secs: u64,
+    /* the last matches, newest first. */
+    pub history: Vec<LudoMatchSummary>,
+}
+
+/* one finished match from the view of one profile. */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, new)]
+#[allow(clippy::too_many_arguments)]
+pub struct LudoMatchSummary {
+    /* unix time in seconds. */
+    pub finished_at: u64,
+    pub place: u8,
+    pub players: u8,
+    pub points: u128,
+    pub captures: u32,
+    pub tokens_lost: u32,
+    pub sixes: u32,
+    pub game_secs: u64,
+}
+
+impl LudoGameStatistics {
+    pub fn average_game_secs(&self) -> u64 {
+        self.total_game_secs.checked_div(self.games as u64).unwrap_or(0)
+    }
+
+    pub fn record(&mut self, summary: LudoMatchSummary) {
+        self.games += 1;
+        if summary.place == 1 {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+        let place = summary.place.max(1) as usize;
+        if self.placements.len() < place {
+            self.placements.resize(place, 0);
+        }
+        self.placements[place - 1] += 1;
+        self.captures += summary.captures;
+        self.tokens_lost += summary.tokens_lost;
+        self.sixes += summary.sixes;
+        self.total_game_secs += summary.game_secs;
+        self.history.insert(0, summary);
+        self.history.truncate(LUDO_MATCH_HISTORY_LENGTH);
+    }
+}
+
+/* final standing of one player in a finished match. */
+#[derive(Serialize, Deserialize, Debug, Clone, new)]
+pub struct LudoGamePlacement {
+    pub place: u8,
+    pub color: LudoColor,
+    pub nickname: Option<String>,
+    pub points: u128,
+}
+
+pub type LudoRoomId = u32;
+
+/* public view of a room as shown in the room list. */
+#[derive(Serialize, Deserialize, Debug, Clone, new)]
+#[allow(clippy::too_many_arguments)]
+pub struct LudoRoomInfo {
+    pub id: LudoRoomId,
+    pub name: String,
+    pub state: LudoGameState,
+    pub players: u8,
+    pub spectators: u8,
+    pub min_players: u8,
+    pub max_players: u8,
+    pub rules: LudoRuleSet,
+    /* difficulty of the bots filling the free seats at match start, none for humans only. */
+    pub bots: Option<LudoBotDifficulty>,
 }
 
 #[derive(Resource, new)]
 pub struct LudoGameConfiguration {
     pub min_players_to_start: u8,
     pub max_players_to_start: u8,
+    pub rules: LudoRuleSet,
+}
+
+pub const LUDO_PROFILES_VERSION: u32 = 3;
+pub const LUDO_LOCAL_PROFILE_VERSION: u32 = 2;
+
+/* profiles.json: version 1 knew no keys, version 2 registers the public key of every profile, version 3 adds statistics. */
+pub const LUDO_PROFILES_SCHEMA: LudoSchema<Vec<LudoGameProfileData>> = LudoSchema {
+    name: "profiles",
+    version: LUDO_PROFILES_VERSION,
+    legacy_version: |document| {
+        let list = serde_json::from_str::<Vec<HashMap<String, IgnoredAny>>>(document)?;
+        Ok(if list.iter().any(|profile| !profile.contains_key("public_key")) { 1 } else { 2 })
+    },
+    read: |version, data| match version {
+        1 => Ok(serde_json::from_str::<Vec<LudoGameProfileDataV1>>(data)?.into_iter().map(LudoGameProfileDataV2::from).map(LudoGameProfileData::from).collect()),
+        2 => Ok(serde_json::from_str::<Vec<LudoGameProfileDataV2>>(data)?.into_iter().map(LudoGameProfileData::from).collect()),
+        _ => Ok(serde_json::from_str::<Vec<LudoGameProfileData>>(data)?),
+    },
+};
+
+/* game-profile.json: version 2 added the profile keypair. */
+const LUDO_LOCAL_PROFILE_SCHEMA: LudoSchema<LudoGameLocalProfile> = LudoSchema {
+    name: "game profile",
+    version: LUDO_LOCAL_PROFILE_VERSION,
+    legacy_version: |document| {
+        let profile = serde_json::from_str::<HashMap<String, IgnoredAny>>(document)?;
+        Ok(if profile.contains_key("public_key") || profile.contains_key("secret_key") { 2 } else { 1 })
+    },
+    read: |version, data| match version {
+        1 => Ok(LudoGameLocalProfile::from(serde_json::from_str::<LudoGameProfileV1>(data)?)),
+        _ => Ok(serde_json::from_str::<LudoGameLocalProfile>(data)?),
+    },
+};
+
+#[derive(Deserialize)]
+struct LudoGameProfileDataV1 {
+    unique_id: [u8; 16],
+    points: u128,
+}
+
+#[derive(Deserialize)]
+struct LudoGameProfileDataV2 {
+    unique_id: [u8; 16],
+    points: u128,
+    public_key: Option<[u8; 32]>,
+}
+
+impl From<LudoGameProfileDataV1> for LudoGameProfileDataV2 {
+    fn from(profile: LudoGameProfileDataV1) -> Self {
+        LudoGameProfileDataV2 { unique_id: profile.unique_id, points: profile.points, public_key: None }
+    }
+}
+
+impl From<LudoGameProfileDataV2> for LudoGameProfileData {
+    fn from(profile: LudoGameProfileDataV2) -> Self {
+        LudoGameProfileData { unique_id: profile.unique_id, points: profile.points, public_key: profile.public_key, statistics: LudoGameStatistics::default() }
+    }
+}
+
+#[derive(Deserialize)]
+struct LudoGameProfileV1 {
+    unique_id: [u8; 16],
+    nickname: String,
+    age: u8,
+}
+
+/* the keypair is generated by the load, a missing secret key is all it takes. */
+impl From<LudoGameProfileV1> for LudoGameLocalProfile {
+    fn from(profile: LudoGameProfileV1) -> Self {
+        LudoGameLocalProfile {
+            profile: LudoGameProfile { unique_id: profile.unique_id, nickname: profile.nickname, age: profile.age, public_key: [0; 32] },
+            secret_key: None,
+        }
+    }
 }
 
 impl LudoGameProfileData {
+    /* a missing file starts with no profiles, a damaged one is recovered from its last good copy. Older versions are
+       written back migrated, the replaced file stays behind as the last good copy. */
     pub fn load_from_file(file: &str) -> Result<Vec<LudoGameProfileData>> {
-        let file_path = PathBuf::from(file);
-        if file_path.exists() {
-            let mut open_options = OpenOptions::new().read(true).write(true).create(true).open(file_path)?;
-            if !file.is_empty() {
-                let mut buf = String::new();
-                open_options.read_to_string(&mut buf)?;
-                Ok(serde_json::from_str::<Vec<LudoGameProfileData>>(&buf)?)
-            } else {
-                open_options.write_all(serde_json::to_string::<Vec<LudoGameProfileData>>(&vec![])?.as_bytes()).expect("Could not write data");
+        match storage::read_with(file, |document| LUDO_PROFILES_SCHEMA.migrate(document))? {
+            Some((list, version)) => {
+                if let Some(version) = version {
+                    bevy::log::info!("rewriting {} of profiles version {} as version {}.", file, version, LUDO_PROFILES_VERSION);
+                    Self::load_to_file(file, list.clone())?;
+                }
+                Ok(list)
+            }
+            None => {
+                Self::load_to_file(file, vec![])?;
                 Ok(vec![])
             }
-        } else {
-            let mut open_options = OpenOptions::new().write(true).create(true).open(file_path)?;
-            open_options.write_all(serde_json::to_string::<Vec<LudoGameProfileData>>(&vec![])?.as_bytes()).expect("Could not write data");
-            Ok(vec![])
         }
     }
 
     pub fn load_to_file(file: &str, list: Vec<LudoGameProfileData>) -> Result<()> {
-        let file_path = PathBuf::from(file);
-        let mut open_options = OpenOptions::new().write(true).create(true).open(file_path)?;
-        open_options.write_all(serde_json::to_string(&list)?.as_bytes()).expect("Could not write data");
-        Ok(())
+        storage::write(file, &LUDO_PROFILES_SCHEMA.document(&list))
     }
 }
 
 impl LudoGameProfile {
-    pub fn load_from_file(file: &str) -> Result<LudoGameProfile> {
-        let file_path = PathBuf::from(file);
-        if file_path.exists() {
-            let mut open_options = OpenOptions::new().read(true).write(true).create(true).open(file_path)?;
-            if !file.is_empty() {
-                let mut buf = String::new();
-                open_options.read_to_string(&mut buf)?;
-                Ok(serde_json::from_str::<LudoGameProfile>(&buf)?)
-            } else {
-                /* todo: error */
-                Ok(LudoGameProfile {
-                    unique_id: *Uuid::new_v4().as_bytes(),
-                    nickname: "default".to_string(),
-                    age: 17,
-                })
+    /* loads the own profile together with its secret key, profiles without a keypair get one. A damaged file is
+       recovered from its last good copy, the identity of the player must not get lost. */
+    pub fn load_from_file(file: &str) -> Result<(LudoGameProfile, LudoGameProfileKey)> {
+        if let Some((local_profile, version)) = storage::read_with(file, |document| LUDO_LOCAL_PROFILE_SCHEMA.migrate(document))? {
+            match local_profile.secret_key {
+                Some(secret_key) => {
+                    if version.is_some() {
+                        Self::load_to_file(file, local_profile.profile.clone(), &LudoGameProfileKey(secret_key))?;
+                    }
+                    Ok((local_profile.profile, LudoGameProfileKey(secret_key)))
+                }
+                None => {
+                    let mut profile = local_profile.profile;
+                    let secret_key = security::generate_key();
+                    profile.public_key = security::profile_public_key(&secret_key);
+                    Self::load_to_file(file, profile.clone(), &LudoGameProfileKey(secret_key))?;
+                    Ok((profile, LudoGameProfileKey(secret_key)))
+                }
             }
         } else {
-            let mut open_options = OpenOptions::new().write(true).create(true).open(file_path)?;
-            let unique_id = *Uuid::new_v4().as_bytes();
-            open_options.write_all(serde_json::to_string::<LudoGameProfile>(&LudoGameProfile {
-                unique_id: unique_id.clone(),
-                nickname: "default".to_string(),
-                age: 17,
-            })?.as_bytes()).expect("Could not write data");
-            Ok(LudoGameProfile {
-                unique_id,
+            let secret_key = security::generate_key();
+            let profile = LudoGameProfile {
+                unique_id: *Uuid::new_v4().as_bytes(),
                 nickname: "default".to_string(),
                 age: 17,
-            })
+                public_key: security::profile_public_key(&secret_key),
+            };
+            Self::load_to_file(file, profile.clone(), &LudoGameProfileKey(secret_key))?;
+            Ok((profile, LudoGameProfileKey(secret_key)))
         }
     }
 
-    pub fn load_to_file(file: &str, profile: LudoGameProfile) -> Result<()> {
-        let file_path = PathBuf::from(file);
-        let mut open_options = OpenOptions::new().write(true).create(true).open(file_path)?;
-        open_options.write_all(serde_json::to_string(&profile)?.as_bytes()).expect("Could not write data");
-        Ok(())
+    /* the profile of a file of any known version, without its key and without writing anything. */
+    pub fn read_from_file(file: &str) -> Result<LudoGameProfile> {
+        let (local_profile, _) = storage::read_with(file, |document| LUDO_LOCAL_PROFILE_SCHEMA.migrate(document))?.ok_or(anyhow!("{} does not exist", file))?;
+        Ok(local_profile.profile)
+    }
+
+    pub fn load_to_file(file: &str, profile: LudoGameProfile, key: &LudoGameProfileKey) -> Result<()> {
+        storage::write(file, &LUDO_LOCAL_PROFILE_SCHEMA.document(&LudoGameLocalProfile { profile, secret_key: Some(key.0) }))
     }
 }
\ No newline at end of file
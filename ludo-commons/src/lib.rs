@@ -1,10 +1,15 @@
 pub mod security;
 pub mod packets;
 pub mod game;
+pub mod rules;
+pub mod dice;
+pub mod protocol;
+pub mod bot;
+pub mod storage;
+pub mod schema;
 
 use derive_new::new;
-pub use crate::security::SECRET_KEY;
-pub use crate::packets::{LudoPacket, LudoPacketType};
+pub use crate::packets::{LudoClientMessage, LudoMessage, LudoPacket, LudoPacketDispatcher, LudoPacketType, LudoServerMessage};
 
 #[derive(new)]
 pub struct Pair<F, S>(pub F, pub S);
\ No newline at end of file
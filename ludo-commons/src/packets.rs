@@ -1,9 +1,12 @@
-use std::any::type_name;
-use std::fmt::Display;
 use serde::{Deserialize, Serialize};
-use anyhow::{Error, Result};
+use serde::de::DeserializeOwned;
+use anyhow::Result;
 use derive_new::new;
-use crate::game::{LudoGameProfile, LudoGameProfileData};
+use crate::bot::LudoBotDifficulty;
+use crate::dice::{LudoDiceCommitment, LudoDiceRoll, LudoDiceSeed};
+use crate::game::{LudoGamePlacement, LudoGameProfile, LudoGameProfileData, LudoGameStatistics, LudoRoomId, LudoRoomInfo};
+use crate::rules::{LudoBoardState, LudoColor, LudoMove, LudoRuleSet};
+use crate::security::{LudoHandshakeResponse, LudoNonce, LudoProfileSignature};
 use crate::LudoPacketType::{Income, Outcome};
 
 pub enum LudoPacketType {
@@ -17,39 +20,103 @@ pub enum LudoPacketType {
 pub trait LudoPacket: Serialize + for<'de> Deserialize<'de> {
     /* only method to be implemented to determine the type of the packet. */
     fn packet_type(&self) -> LudoPacketType;
+}
 
-    //noinspection ALL
-    fn into_string<T>(&self) -> Result<String> where T: LudoPacket {
-        Ok(format!("type: {0}, data: #[{1}]#", std::any::type_name::<T>(), serde_json::to_string(&self)?))
+/* Envelope around every packet of one direction. Only envelopes go over the wire, encoded with bincode, so decoding is a single match on the variant. */
+pub trait LudoMessage: Serialize + DeserializeOwned {
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
     }
 
-    fn make_packet<T>(buf: String) -> Result<T> where T: LudoPacket {
-        if buf.contains(type_name::<T>()) {
-            serde_json::from_str(regex(buf)?.as_str()).map_err(|e| e.into())
-        } else {
-            Err(Error::msg(format!("Invalid packet type: {}", buf)))
-        }
+    fn decode(buf: &[u8]) -> Result<Self> {
+        Ok(bincode::deserialize(buf)?)
     }
 }
 
-fn regex<T>(input: T) -> Result<T> where T: Display + ToString + for<'a> From<&'a str> {
-    let input = input.to_string();
-    if let Some(start) = input.find("data: #[") {
-        let start = start + "data: #[".len();
-        if let Some(end) = input[start..].find("]#") {
-            let data = &input[start..start + end];
-            Ok(data.into())
-        } else {
-            Err(Error::msg(format!("invalid input: {}", input)))
+/* receives every decoded packet exactly once. Each side implements it to turn packets into its own typed events. */
+pub trait LudoPacketDispatcher {
+    fn dispatch<T>(&mut self, packet: T) where T: LudoPacket + Send + Sync + 'static;
+}
+
+/* declares an envelope enum with one variant per packet, a `From` conversion for each of them and the dispatch table. */
+macro_rules! ludo_message {
+    ($(#[$meta:meta])* $message:ident { $($variant:ident($packet:ty)),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        pub enum $message {
+            $($variant($packet)),*
+        }
+
+        impl LudoMessage for $message {}
+
+        impl $message {
+            pub fn dispatch<D>(self, dispatcher: &mut D) where D: LudoPacketDispatcher {
+                match self {
+                    $($message::$variant(packet) => dispatcher.dispatch(packet)),*
+                }
+            }
         }
-    } else {
-        Err(Error::msg(format!("invalid input: {}", input)))
+
+        $(impl From<$packet> for $message {
+            fn from(packet: $packet) -> Self {
+                $message::$variant(packet)
+            }
+        })*
+    };
+}
+
+ludo_message! {
+    /* every packet sent from one or more clients to the server. */
+    LudoClientMessage {
+        Handshake(LudoGameIncomeHandshakePacket),
+        Profile(LudoGameIncomeProfilePacket),
+        Roll(LudoGameIncomeRollPacket),
+        Move(LudoGameIncomeMovePacket),
+        ListRooms(LudoGameIncomeListRoomsPacket),
+        CreateRoom(LudoGameIncomeCreateRoomPacket),
+        JoinRoom(LudoGameIncomeJoinRoomPacket),
+        LeaveRoom(LudoGameIncomeLeaveRoomPacket),
+        SpectateRoom(LudoGameIncomeSpectateRoomPacket),
+        FindMatch(LudoGameIncomeFindMatchPacket),
+        CancelMatch(LudoGameIncomeCancelMatchPacket),
+        Statistics(LudoGameIncomeStatisticsPacket),
+    }
+}
+
+ludo_message! {
+    /* every packet sent from the server to one or more clients. */
+    LudoServerMessage {
+        HandshakeCallback(LudoGameOutcomeHandshakeCallbackPacket),
+        Profile(LudoGameOutcomeProfilePacket),
+        Disconnect(LudoGameOutcomeDisconnectPacket),
+        GameStart(LudoGameOutcomeGameStartPacket),
+        PlayerProfiles(LudoGameOutcomePlayerProfilesPacket),
+        ServerData(LudoGameOutcomeServerDataPacket),
+        Board(LudoGameOutcomeBoardPacket),
+        Turn(LudoGameOutcomeTurnPacket),
+        DiceCommitment(LudoGameOutcomeDiceCommitmentPacket),
+        DiceRoll(LudoGameOutcomeDiceRollPacket),
+        DiceReveal(LudoGameOutcomeDiceRevealPacket),
+        GameResult(LudoGameOutcomeGameResultPacket),
+        HandshakeChallenge(LudoGameOutcomeHandshakeChallengePacket),
+        RoomList(LudoGameOutcomeRoomListPacket),
+        RoomJoined(LudoGameOutcomeRoomJoinedPacket),
+        RoomRejected(LudoGameOutcomeRoomRejectedPacket),
+        Queued(LudoGameOutcomeQueuedPacket),
+        MatchFound(LudoGameOutcomeMatchFoundPacket),
+        Snapshot(LudoGameOutcomeSnapshotPacket),
+        TurnDeadline(LudoGameOutcomeTurnDeadlinePacket),
+        Statistics(LudoGameOutcomeStatisticsPacket),
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameIncomeHandshakePacket {
-    pub key: [u8; 32],
+    /* HMAC of the challenge nonce with the shared handshake key. */
+    pub response: LudoHandshakeResponse,
+    pub protocol_version: u16,
+    /* build of the client, only used to explain a rejection. */
+    pub client_build: String,
 }
 
 impl LudoPacket for LudoGameIncomeHandshakePacket {
@@ -58,6 +125,18 @@ impl LudoPacket for LudoGameIncomeHandshakePacket {
     }
 }
 
+/* sent on connect, the client answers with a `LudoGameIncomeHandshakePacket`. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeHandshakeChallengePacket {
+    pub nonce: LudoNonce,
+}
+
+impl LudoPacket for LudoGameOutcomeHandshakeChallengePacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameOutcomeHandshakeCallbackPacket {
 
@@ -72,6 +151,8 @@ impl LudoPacket for LudoGameOutcomeHandshakeCallbackPacket {
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameIncomeProfilePacket {
     pub profile: LudoGameProfile,
+    /* signature of the unique id and the handshake nonce with the key of the profile. */
+    pub signature: LudoProfileSignature,
 }
 
 impl LudoPacket for LudoGameIncomeProfilePacket {
@@ -104,7 +185,7 @@ impl LudoPacket for LudoGameOutcomeDisconnectPacket {
 
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameOutcomeGameStartPacket {
-
+    pub color: LudoColor,
 }
 
 impl LudoPacket for LudoGameOutcomeGameStartPacket {
@@ -116,6 +197,8 @@ impl LudoPacket for LudoGameOutcomeGameStartPacket {
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameOutcomePlayerProfilesPacket {
     pub list: Vec<LudoGameProfile>,
+    /* watching the room without a seat. */
+    pub spectators: Vec<LudoGameProfile>,
 }
 
 impl LudoPacket for LudoGameOutcomePlayerProfilesPacket {
@@ -127,10 +210,305 @@ impl LudoPacket for LudoGameOutcomePlayerProfilesPacket {
 #[derive(Debug, Clone, Serialize, Deserialize, new)]
 pub struct LudoGameOutcomeServerDataPacket {
     pub min_players: usize,
+    pub rules: LudoRuleSet,
 }
 
 impl LudoPacket for LudoGameOutcomeServerDataPacket {
     fn packet_type(&self) -> LudoPacketType {
         Outcome
     }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeRollPacket {
+
+}
+
+impl LudoPacket for LudoGameIncomeRollPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeMovePacket {
+    pub token: usize,
+}
+
+impl LudoPacket for LudoGameIncomeMovePacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeBoardPacket {
+    pub board: LudoBoardState,
+}
+
+impl LudoPacket for LudoGameOutcomeBoardPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* tells every client whose turn it is. Without a roll the active player is asked to roll, otherwise to pick one of the moves. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeTurnPacket {
+    pub color: LudoColor,
+    pub roll: Option<u8>,
+    pub moves: Vec<LudoMove>,
+}
+
+impl LudoPacket for LudoGameOutcomeTurnPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* published at match start when commit-reveal is enabled: the sha256 hash of the dice seed. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeDiceCommitmentPacket {
+    pub commitment: LudoDiceCommitment,
+}
+
+impl LudoPacket for LudoGameOutcomeDiceCommitmentPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeDiceRollPacket {
+    pub color: LudoColor,
+    pub roll: LudoDiceRoll,
+}
+
+impl LudoPacket for LudoGameOutcomeDiceRollPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeDiceRevealPacket {
+    pub seed: LudoDiceSeed,
+}
+
+impl LudoPacket for LudoGameOutcomeDiceRevealPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeGameResultPacket {
+    pub placements: Vec<LudoGamePlacement>,
+}
+
+impl LudoPacket for LudoGameOutcomeGameResultPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeListRoomsPacket {
+
+}
+
+impl LudoPacket for LudoGameIncomeListRoomsPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+/* opens a new room with the sender as its first member, missing values fall back to the server defaults. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeCreateRoomPacket {
+    pub name: String,
+    pub min_players: Option<u8>,
+    pub max_players: Option<u8>,
+    pub rules: Option<LudoRuleSet>,
+    pub bots: Option<LudoBotDifficulty>,
+}
+
+impl LudoPacket for LudoGameIncomeCreateRoomPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeJoinRoomPacket {
+    pub room: LudoRoomId,
+}
+
+impl LudoPacket for LudoGameIncomeJoinRoomPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+/* watches a room in any state, without a seat and without input rights. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeSpectateRoomPacket {
+    pub room: LudoRoomId,
+}
+
+impl LudoPacket for LudoGameIncomeSpectateRoomPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeLeaveRoomPacket {
+
+}
+
+impl LudoPacket for LudoGameIncomeLeaveRoomPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeRoomListPacket {
+    pub rooms: Vec<LudoRoomInfo>,
+}
+
+impl LudoPacket for LudoGameOutcomeRoomListPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeRoomJoinedPacket {
+    pub room: LudoRoomInfo,
+}
+
+impl LudoPacket for LudoGameOutcomeRoomJoinedPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* answer to a room request which could not be fulfilled, the connection stays open. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeRoomRejectedPacket {
+    pub reason: String,
+}
+
+impl LudoPacket for LudoGameOutcomeRoomRejectedPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* enters the quick play queue, missing preferences accept whatever the server defaults to. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeFindMatchPacket {
+    pub players: Option<u8>,
+    pub rules: Option<LudoRuleSet>,
+}
+
+impl LudoPacket for LudoGameIncomeFindMatchPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeCancelMatchPacket {
+
+}
+
+impl LudoPacket for LudoGameIncomeCancelMatchPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+/* confirms the queue entry with the player count the client waits for. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeQueuedPacket {
+    pub players: u8,
+    pub queued: u32,
+}
+
+impl LudoPacket for LudoGameOutcomeQueuedPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* the queue placed the client into this room together with the listed players. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeMatchFoundPacket {
+    pub room: LudoRoomInfo,
+    pub players: Vec<String>,
+}
+
+impl LudoPacket for LudoGameOutcomeMatchFoundPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* everything a client needs to resume its seat after a reconnect or to watch a running match, spectators have no colour. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeSnapshotPacket {
+    pub room: LudoRoomInfo,
+    pub color: Option<LudoColor>,
+    pub board: LudoBoardState,
+    pub turn: LudoGameOutcomeTurnPacket,
+    pub dice_commitment: Option<LudoDiceCommitment>,
+    /* every roll of the match so far, needed to verify the dice once the seed is revealed. */
+    pub rolls: Vec<LudoDiceRoll>,
+}
+
+impl LudoPacket for LudoGameOutcomeSnapshotPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* time left for the next action of the active colour, the server acts for it once the time is up. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeTurnDeadlinePacket {
+    pub color: LudoColor,
+    pub remaining_ms: u64,
+}
+
+impl LudoPacket for LudoGameOutcomeTurnDeadlinePacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
+}
+
+/* asks for the statistics of a profile, none asks for the own ones. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameIncomeStatisticsPacket {
+    pub unique_id: Option<[u8; 16]>,
+}
+
+impl LudoPacket for LudoGameIncomeStatisticsPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Income
+    }
+}
+
+/* statistics of the asked profile, none if the server does not know it. */
+#[derive(Debug, Clone, Serialize, Deserialize, new)]
+pub struct LudoGameOutcomeStatisticsPacket {
+    pub unique_id: [u8; 16],
+    pub statistics: Option<LudoGameStatistics>,
+}
+
+impl LudoPacket for LudoGameOutcomeStatisticsPacket {
+    fn packet_type(&self) -> LudoPacketType {
+        Outcome
+    }
 }
\ No newline at end of file
@@ -0,0 +1,14 @@
+/* bumped whenever the wire format or the meaning of a packet changes. */
+pub const LUDO_PROTOCOL_VERSION: u16 = 8;
+
+/* netcode protocol id, clients of another protocol version are refused before any packet is read. */
+pub const LUDO_PROTOCOL_ID: u64 = protocol_id(LUDO_PROTOCOL_VERSION);
+
+/* "LUDO" in the upper half, the protocol version in the lower bits. */
+pub const fn protocol_id(version: u16) -> u64 {
+    0x4C55_444F_0000_0000 | version as u64
+}
+
+pub fn is_compatible(version: u16) -> bool {
+    version == LUDO_PROTOCOL_VERSION
+}
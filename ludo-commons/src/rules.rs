@@ -0,0 +1,623 @@
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/* Pure ludo rules. Nothing in this module depends on bevy, so the server and the client share one source of truth. */
+
+/* squares on the shared outer track. */
+pub const LUDO_TRACK_LENGTH: u8 = 52;
+/* squares between the coloured start square of two neighbouring players. */
+pub const LUDO_TRACK_SEGMENT: u8 = 13;
+/* steps a token walks on the outer track before it turns into its home column (0..=50). */
+pub const LUDO_TRACK_STEPS: u8 = 51;
+/* squares inside the home column, the finish is one step behind the last one. */
+pub const LUDO_HOME_COLUMN_LENGTH: u8 = 5;
+/* steps from the start square up to the finish. */
+pub const LUDO_FINISH_PROGRESS: u8 = LUDO_TRACK_STEPS + LUDO_HOME_COLUMN_LENGTH;
+pub const LUDO_TOKENS_PER_PLAYER: usize = 4;
+pub const LUDO_ENTRY_ROLL: u8 = 6;
+/* absolute track squares where no token can be captured: the four start squares and the four stars. */
+pub const LUDO_SAFE_SQUARES: [u8; 8] = [0, 8, 13, 21, 26, 34, 39, 47];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LudoColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+}
+
+/* where a single token currently is. Track and home column positions are relative to the owner's start square. */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LudoTokenPosition {
+    Yard,
+    Track(u8),
+    HomeColumn(u8),
+    Finished,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct LudoPlayerBoard {
+    pub color: LudoColor,
+    pub tokens: Vec<LudoTokenPosition>,
+}
+
+/* house rules of a match. Every field falls back to the classic rules when missing in the rules file. */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(default)]
+pub struct LudoRuleSet {
+    /* rolls which let a token leave the yard, classic is only a six. */
+    pub entry_rolls: Vec<u8>,
+    pub capture_on_safe_squares: bool,
+    /* two tokens of the same colour on one square can not be passed or landed on by other colours. */
+    pub blockades: bool,
+    pub bonus_roll_on_capture: bool,
+    pub bonus_roll_on_finish: bool,
+    /* without an exact roll a token overshooting the finish simply finishes. */
+    pub exact_roll_to_finish: bool,
+    pub tokens_per_player: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct LudoBoardState {
+    pub rules: LudoRuleSet,
+    pub players: Vec<LudoPlayerBoard>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LudoMoveKind {
+    LeaveYard,
+    Advance,
+    EnterHomeColumn,
+    Finish,
+}
+
+/* a single legal token move for one roll, including every opposing token it would send back to the yard. */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct LudoMove {
+    pub player: LudoColor,
+    pub token: usize,
+    pub roll: u8,
+    pub from: LudoTokenPosition,
+    pub to: LudoTokenPosition,
+    pub kind: LudoMoveKind,
+    pub captures: Vec<(LudoColor, usize)>,
+}
+
+/* what the active player can do with a roll. A pass means the roll is used up without moving. */
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum LudoTurnOptions {
+    Moves(Vec<LudoMove>),
+    Pass,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LudoRuleError {
+    InvalidRoll(u8),
+    UnknownPlayer(LudoColor),
+    UnknownToken(usize),
+    /* the token is still in the yard and the roll does not allow it to enter the track. */
+    EntryRollRequired(u8),
+    AlreadyFinished(usize),
+    /* the roll would carry the token past the finish. */
+    Overshoot(u8),
+    /* a blockade stands on the given absolute square. */
+    Blocked(u8),
+}
+
+impl Display for LudoRuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LudoRuleError::InvalidRoll(roll) => write!(f, "invalid roll: {}", roll),
+            LudoRuleError::UnknownPlayer(color) => write!(f, "player {:?} is not on the board", color),
+            LudoRuleError::UnknownToken(token) => write!(f, "token {} does not exist", token),
+            LudoRuleError::EntryRollRequired(roll) => write!(f, "a roll of {} can not leave the yard", roll),
+            LudoRuleError::AlreadyFinished(token) => write!(f, "token {} already finished", token),
+            LudoRuleError::Overshoot(roll) => write!(f, "a roll of {} overshoots the finish", roll),
+            LudoRuleError::Blocked(square) => write!(f, "square {} is blocked", square),
+        }
+    }
+}
+
+impl std::error::Error for LudoRuleError {}
+
+impl LudoColor {
+    pub const ALL: [LudoColor; 4] = [LudoColor::Red, LudoColor::Green, LudoColor::Yellow, LudoColor::Blue];
+
+    pub fn index(&self) -> usize {
+        match self {
+            LudoColor::Red => 0,
+            LudoColor::Green => 1,
+            LudoColor::Yellow => 2,
+            LudoColor::Blue => 3,
+        }
+    }
+
+    /* absolute track square on which tokens of this colour enter the board. */
+    pub fn start_square(&self) -> u8 {
+        self.index() as u8 * LUDO_TRACK_SEGMENT
+    }
+
+    /* colours used for a match of the given size, two players sit opposite to each other. */
+    pub fn seating(players: usize) -> Vec<LudoColor> {
+        match players {
+            0 => vec![],
+            1 => vec![LudoColor::Red],
+            2 => vec![LudoColor::Red, LudoColor::Yellow],
+            3 => vec![LudoColor::Red, LudoColor::Green, LudoColor::Yellow],
+            _ => LudoColor::ALL.to_vec(),
+        }
+    }
+}
+
+impl LudoTokenPosition {
+    /* steps walked since leaving the yard, none while the token waits in the yard. */
+    pub fn progress(&self) -> Option<u8> {
+        match self {
+            LudoTokenPosition::Yard => None,
+            LudoTokenPosition::Track(step) => Some(*step),
+            LudoTokenPosition::HomeColumn(step) => Some(LUDO_TRACK_STEPS + *step),
+            LudoTokenPosition::Finished => Some(LUDO_FINISH_PROGRESS),
+        }
+    }
+
+    pub fn from_progress(progress: u8) -> LudoTokenPosition {
+        if progress >= LUDO_FINISH_PROGRESS {
+            LudoTokenPosition::Finished
+        } else if progress >= LUDO_TRACK_STEPS {
+            LudoTokenPosition::HomeColumn(progress - LUDO_TRACK_STEPS)
+        } else {
+            LudoTokenPosition::Track(progress)
+        }
+    }
+
+    /* absolute square on the shared track, only tokens on the outer track can meet other colours. */
+    pub fn absolute_square(&self, color: LudoColor) -> Option<u8> {
+        match self {
+            LudoTokenPosition::Track(step) => Some((color.start_square() + *step) % LUDO_TRACK_LENGTH),
+            _ => None,
+        }
+    }
+}
+
+impl Default for LudoRuleSet {
+    fn default() -> Self {
+        Self {
+            entry_rolls: vec![LUDO_ENTRY_ROLL],
+            capture_on_safe_squares: false,
+            blockades: false,
+            bonus_roll_on_capture: false,
+            bonus_roll_on_finish: false,
+            exact_roll_to_finish: true,
+            tokens_per_player: LUDO_TOKENS_PER_PLAYER,
+        }
+    }
+}
+
+impl LudoRuleSet {
+    pub fn validate(&self) -> Result<()> {
+        if self.entry_rolls.is_empty() || self.entry_rolls.iter().any(|roll| !(1..=6).contains(roll)) {
+            return Err(Error::msg(format!("entry rolls must be between 1 and 6: {:?}", self.entry_rolls)));
+        }
+        if !(1..=LUDO_TOKENS_PER_PLAYER).contains(&self.tokens_per_player) {
+            return Err(Error::msg(format!("tokens per player must be between 1 and {}: {}", LUDO_TOKENS_PER_PLAYER, self.tokens_per_player)));
+        }
+        Ok(())
+    }
+
+    /* a six always grants another roll, captures and finishes only when the house rules say so. */
+    pub fn grants_extra_roll(&self, ludo_move: &LudoMove) -> bool {
+        ludo_move.roll == 6
+            || (self.bonus_roll_on_capture && !ludo_move.captures.is_empty())
+            || (self.bonus_roll_on_finish && ludo_move.kind == LudoMoveKind::Finish)
+    }
+
+    pub fn load_from_file(file: &str) -> Result<LudoRuleSet> {
+        let file_path = PathBuf::from(file);
+        if file_path.exists() {
+            let mut open_options = OpenOptions::new().read(true).open(file_path)?;
+            let mut buf = String::new();
+            open_options.read_to_string(&mut buf)?;
+            let rules = serde_json::from_str::<LudoRuleSet>(&buf)?;
+            rules.validate()?;
+            Ok(rules)
+        } else {
+            let mut open_options = OpenOptions::new().write(true).create(true).truncate(true).open(file_path)?;
+            open_options.write_all(serde_json::to_string_pretty(&LudoRuleSet::default())?.as_bytes())?;
+            Ok(LudoRuleSet::default())
+        }
+    }
+}
+
+impl LudoPlayerBoard {
+    pub fn new(color: LudoColor, tokens: usize) -> Self {
+        Self { color, tokens: vec![LudoTokenPosition::Yard; tokens] }
+    }
+
+    pub fn has_finished(&self) -> bool {
+        self.tokens.iter().all(|token| *token == LudoTokenPosition::Finished)
+    }
+}
+
+impl LudoBoardState {
+    pub fn new(colors: &[LudoColor]) -> Self {
+        Self::with_rules(colors, LudoRuleSet::default())
+    }
+
+    pub fn with_rules(colors: &[LudoColor], rules: LudoRuleSet) -> Self {
+        let players = colors.iter().map(|color| LudoPlayerBoard::new(*color, rules.tokens_per_player)).collect();
+        Self { rules, players }
+    }
+
+    pub fn player(&self, color: LudoColor) -> Option<&LudoPlayerBoard> {
+        self.players.iter().find(|player| player.color == color)
+    }
+
+    pub fn player_mut(&mut self, color: LudoColor) -> Option<&mut LudoPlayerBoard> {
+        self.players.iter_mut().find(|player| player.color == color)
+    }
+
+    /* every token standing on the given absolute track square. */
+    pub fn tokens_at(&self, square: u8) -> Vec<(LudoColor, usize)> {
+        let mut found = vec![];
+        self.players.iter().for_each(|player| {
+            player.tokens.iter().enumerate().for_each(|(token, position)| {
+                if position.absolute_square(player.color) == Some(square) {
+                    found.push((player.color, token));
+                }
+            });
+        });
+        found
+    }
+
+    /* a square holding two or more tokens of a colour other than the moving one. */
+    pub fn is_blockade(&self, square: u8, mover: LudoColor) -> bool {
+        let tokens = self.tokens_at(square);
+        LudoColor::ALL.iter().filter(|color| **color != mover).any(|color| tokens.iter().filter(|(owner, _)| owner == color).count() >= 2)
+    }
+
+    /* validates a single token move without touching the board. */
+    pub fn resolve_move(&self, player: LudoColor, token: usize, roll: u8) -> Result<LudoMove, LudoRuleError> {
+        if !(1..=6).contains(&roll) {
+            return Err(LudoRuleError::InvalidRoll(roll));
+        }
+        let board = self.player(player).ok_or(LudoRuleError::UnknownPlayer(player))?;
+        let from = *board.tokens.get(token).ok_or(LudoRuleError::UnknownToken(token))?;
+        let to = match from.progress() {
+            None if self.rules.entry_rolls.contains(&roll) => LudoTokenPosition::Track(0),
+            None => return Err(LudoRuleError::EntryRollRequired(roll)),
+            Some(LUDO_FINISH_PROGRESS) => return Err(LudoRuleError::AlreadyFinished(token)),
+            Some(progress) if progress + roll > LUDO_FINISH_PROGRESS && self.rules.exact_roll_to_finish => return Err(LudoRuleError::Overshoot(roll)),
+            Some(progress) => LudoTokenPosition::from_progress(progress + roll),
+        };
+        if self.rules.blockades {
+            /* every outer track square the token touches, from the first step up to where it lands. */
+            let path = match from.progress() {
+                None => 0..=0,
+                Some(progress) => progress + 1..=(progress + roll).min(LUDO_TRACK_STEPS - 1),
+            };
+            for step in path {
+                let square = (player.start_square() + step) % LUDO_TRACK_LENGTH;
+                if self.is_blockade(square, player) {
+                    return Err(LudoRuleError::Blocked(square));
+                }
+            }
+        }
+        let kind = match (from, to) {
+            (LudoTokenPosition::Yard, _) => LudoMoveKind::LeaveYard,
+            (_, LudoTokenPosition::Finished) => LudoMoveKind::Finish,
+            (LudoTokenPosition::Track(_), LudoTokenPosition::HomeColumn(_)) => LudoMoveKind::EnterHomeColumn,
+            _ => LudoMoveKind::Advance,
+        };
+
+        let mut captures = vec![];
+        if let Some(square) = to.absolute_square(player) {
+            if self.rules.capture_on_safe_squares || !LUDO_SAFE_SQUARES.contains(&square) {
+                captures = self.tokens_at(square).into_iter().filter(|(color, _)| *color != player).collect();
+            }
+        }
+        Ok(LudoMove { player, token, roll, from, to, kind, captures })
+    }
+
+    /* every legal move of the active player for the given roll, or a pass when none exists. */
+    pub fn legal_moves(&self, player: LudoColor, roll: u8) -> LudoTurnOptions {
+        let moves = match self.player(player) {
+            Some(board) => (0..board.tokens.len()).filter_map(|token| self.resolve_move(player, token, roll).ok()).collect::<Vec<LudoMove>>(),
+            None => vec![],
+        };
+        if moves.is_empty() {
+            LudoTurnOptions::Pass
+        } else {
+            LudoTurnOptions::Moves(moves)
+        }
+    }
+
+    /* applies a move previously produced by `resolve_move` or `legal_moves`. */
+    pub fn apply(&self, ludo_move: &LudoMove) -> LudoBoardState {
+        let mut state = self.clone();
+        for (color, captured) in &ludo_move.captures {
+            if let Some(board) = state.player_mut(*color) {
+                board.tokens[*captured] = LudoTokenPosition::Yard;
+            }
+        }
+        if let Some(board) = state.player_mut(ludo_move.player) {
+            board.tokens[ludo_move.token] = ludo_move.to;
+        }
+        state
+    }
+
+    pub fn apply_move(&self, player: LudoColor, token: usize, roll: u8) -> Result<LudoBoardState, LudoRuleError> {
+        Ok(self.apply(&self.resolve_move(player, token, roll)?))
+    }
+}
+
+pub fn apply_move(state: &LudoBoardState, player: LudoColor, token: usize, roll: u8) -> Result<LudoBoardState, LudoRuleError> {
+    state.apply_move(player, token, roll)
+}
+
+pub fn legal_moves(state: &LudoBoardState, player: LudoColor, roll: u8) -> LudoTurnOptions {
+    state.legal_moves(player, roll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* a board of the given colours with some tokens already moved, every other token waits in the yard. */
+    fn board_with(colors: &[LudoColor], rules: LudoRuleSet, tokens: &[(LudoColor, usize, LudoTokenPosition)]) -> LudoBoardState {
+        let mut board = LudoBoardState::with_rules(colors, rules);
+        for (color, token, position) in tokens {
+            board.player_mut(*color).unwrap().tokens[*token] = *position;
+        }
+        board
+    }
+
+    /* the track position of a colour standing on the given absolute square. */
+    fn track_at(color: LudoColor, square: u8) -> LudoTokenPosition {
+        LudoTokenPosition::Track((square + LUDO_TRACK_LENGTH - color.start_square()) % LUDO_TRACK_LENGTH)
+    }
+
+    #[test]
+    fn tokens_leave_the_yard_only_on_the_entry_roll() {
+        let board = LudoBoardState::new(&[LudoColor::Red, LudoColor::Yellow]);
+        for roll in 1..LUDO_ENTRY_ROLL {
+            assert_eq!(board.resolve_move(LudoColor::Red, 0, roll), Err(LudoRuleError::EntryRollRequired(roll)));
+        }
+        let ludo_move = board.resolve_move(LudoColor::Red, 0, LUDO_ENTRY_ROLL).unwrap();
+        assert_eq!((ludo_move.from, ludo_move.to, ludo_move.kind), (LudoTokenPosition::Yard, LudoTokenPosition::Track(0), LudoMoveKind::LeaveYard));
+        let state = board.apply(&ludo_move);
+        assert_eq!(state.tokens_at(LudoColor::Red.start_square()), vec![(LudoColor::Red, 0)]);
+    }
+
+    #[test]
+    fn landing_on_an_opponent_captures_it() {
+        let board = board_with(&[LudoColor::Red, LudoColor::Green], LudoRuleSet::default(), &[
+            (LudoColor::Red, 0, LudoTokenPosition::Track(1)),
+            (LudoColor::Green, 2, track_at(LudoColor::Green, 4)),
+        ]);
+        let ludo_move = board.resolve_move(LudoColor::Red, 0, 3).unwrap();
+        assert_eq!(ludo_move.captures, vec![(LudoColor::Green, 2)]);
+        let state = board.apply(&ludo_move);
+        assert_eq!(state.player(LudoColor::Green).unwrap().tokens[2], LudoTokenPosition::Yard);
+        assert_eq!(state.tokens_at(4), vec![(LudoColor::Red, 0)]);
+    }
+
+    #[test]
+    fn safe_squares_protect_their_tokens() {
+        for square in LUDO_SAFE_SQUARES {
+            /* a mover far from its home column, so two more steps stay on the outer track. */
+            let mover = if square < LudoColor::Yellow.start_square() { LudoColor::Yellow } else { LudoColor::Red };
+            let board = board_with(&[LudoColor::Red, LudoColor::Green, LudoColor::Yellow], LudoRuleSet::default(), &[
+                (mover, 0, track_at(mover, (square + LUDO_TRACK_LENGTH - 2) % LUDO_TRACK_LENGTH)),
+                (LudoColor::Green, 0, track_at(LudoColor::Green, square)),
+            ]);
+            let ludo_move = board.resolve_move(mover, 0, 2).unwrap();
+            assert!(ludo_move.captures.is_empty(), "captured on safe square {}", square);
+            assert_eq!(board.apply(&ludo_move).tokens_at(square).len(), 2);
+        }
+    }
+
+    #[test]
+    fn own_tokens_are_never_captured() {
+        let board = board_with(&[LudoColor::Red], LudoRuleSet::default(), &[
+            (LudoColor::Red, 0, LudoTokenPosition::Track(1)),
+            (LudoColor::Red, 1, LudoTokenPosition::Track(4)),
+        ]);
+        assert!(board.resolve_move(LudoColor::Red, 0, 3).unwrap().captures.is_empty());
+    }
+
+    #[test]
+    fn the_finish_needs_an_exact_roll() {
+        let board = board_with(&[LudoColor::Red], LudoRuleSet::default(), &[(LudoColor::Red, 0, LudoTokenPosition::HomeColumn(3))]);
+        assert_eq!(board.resolve_move(LudoColor::Red, 0, 3), Err(LudoRuleError::Overshoot(3)));
+        let ludo_move = board.resolve_move(LudoColor::Red, 0, 2).unwrap();
+        assert_eq!((ludo_move.to, ludo_move.kind), (LudoTokenPosition::Finished, LudoMoveKind::Finish));
+        assert_eq!(board.apply(&ludo_move).resolve_move(LudoColor::Red, 0, 1), Err(LudoRuleError::AlreadyFinished(0)));
+    }
+
+    #[test]
+    fn tokens_turn_into_their_home_column() {
+        let board = board_with(&[LudoColor::Red], LudoRuleSet::default(), &[(LudoColor::Red, 0, LudoTokenPosition::Track(LUDO_TRACK_STEPS - 2))]);
+        let ludo_move = board.resolve_move(LudoColor::Red, 0, 3).unwrap();
+        assert_eq!((ludo_move.to, ludo_move.kind), (LudoTokenPosition::HomeColumn(1), LudoMoveKind::EnterHomeColumn));
+    }
+
+    #[test]
+    fn progress_boundaries_of_every_seat() {
+        for color in LudoColor::ALL {
+            let start = LudoTokenPosition::from_progress(0);
+            assert_eq!(start, LudoTokenPosition::Track(0));
+            assert_eq!(start.absolute_square(color), Some(color.start_square()));
+            let last_track = LudoTokenPosition::from_progress(LUDO_TRACK_STEPS - 1);
+            assert_eq!(last_track, LudoTokenPosition::Track(LUDO_TRACK_STEPS - 1));
+            assert_eq!(last_track.absolute_square(color), Some((color.start_square() + LUDO_TRACK_LENGTH - 2) % LUDO_TRACK_LENGTH));
+            assert_eq!(LudoTokenPosition::from_progress(LUDO_TRACK_STEPS), LudoTokenPosition::HomeColumn(0));
+            assert_eq!(LudoTokenPosition::from_progress(LUDO_FINISH_PROGRESS - 1), LudoTokenPosition::HomeColumn(LUDO_HOME_COLUMN_LENGTH - 1));
+            assert_eq!(LudoTokenPosition::from_progress(LUDO_FINISH_PROGRESS), LudoTokenPosition::Finished);
+            assert_eq!(LudoTokenPosition::from_progress(LUDO_FINISH_PROGRESS + 6), LudoTokenPosition::Finished);
+            for progress in 0..=LUDO_FINISH_PROGRESS {
+                let position = LudoTokenPosition::from_progress(progress);
+                assert_eq!(position.progress(), Some(progress));
+                assert_eq!(position.absolute_square(color).is_some(), progress < LUDO_TRACK_STEPS);
+            }
+            assert_eq!(LudoTokenPosition::Yard.progress(), None);
+            assert_eq!(LudoTokenPosition::Yard.absolute_square(color), None);
+        }
+    }
+
+    fn moved_tokens(options: &LudoTurnOptions) -> Vec<usize> {
+        match options {
+            LudoTurnOptions::Moves(moves) => moves.iter().map(|ludo_move| ludo_move.token).collect(),
+            LudoTurnOptions::Pass => vec![],
+        }
+    }
+
+    #[test]
+    fn a_full_yard_passes_without_an_entry_roll() {
+        let board = LudoBoardState::new(&[LudoColor::Red, LudoColor::Yellow]);
+        for roll in 1..LUDO_ENTRY_ROLL {
+            assert_eq!(board.legal_moves(LudoColor::Red, roll), LudoTurnOptions::Pass);
+        }
+        let options = board.legal_moves(LudoColor::Red, LUDO_ENTRY_ROLL);
+        assert_eq!(moved_tokens(&options), vec![0, 1, 2, 3]);
+        let LudoTurnOptions::Moves(moves) = options else { unreachable!() };
+        assert!(moves.iter().all(|ludo_move| ludo_move.kind == LudoMoveKind::LeaveYard));
+    }
+
+    #[test]
+    fn a_blocked_finish_passes() {
+        let board = board_with(&[LudoColor::Red], LudoRuleSet::default(), &[
+            (LudoColor::Red, 0, LudoTokenPosition::HomeColumn(3)),
+            (LudoColor::Red, 1, LudoTokenPosition::Finished),
+        ]);
+        for roll in 3..LUDO_ENTRY_ROLL {
+            assert_eq!(board.legal_moves(LudoColor::Red, roll), LudoTurnOptions::Pass);
+        }
+        assert_eq!(moved_tokens(&board.legal_moves(LudoColor::Red, 2)), vec![0]);
+        assert_eq!(moved_tokens(&board.legal_moves(LudoColor::Red, LUDO_ENTRY_ROLL)), vec![2, 3]);
+    }
+
+    #[test]
+    fn every_movable_token_is_a_candidate() {
+        let board = board_with(&[LudoColor::Red, LudoColor::Green], LudoRuleSet::default(), &[
+            (LudoColor::Red, 0, LudoTokenPosition::Track(4)),
+            (LudoColor::Red, 2, LudoTokenPosition::HomeColumn(2)),
+            (LudoColor::Red, 3, LudoTokenPosition::Finished),
+            (LudoColor::Green, 0, track_at(LudoColor::Green, 7)),
+        ]);
+        let LudoTurnOptions::Moves(moves) = board.legal_moves(LudoColor::Red, 3) else { panic!("expected moves") };
+        assert_eq!(moves.iter().map(|ludo_move| (ludo_move.token, ludo_move.to)).collect::<Vec<_>>(), vec![
+            (0, LudoTokenPosition::Track(7)),
+            (2, LudoTokenPosition::Finished),
+        ]);
+        assert_eq!(moves[0].captures, vec![(LudoColor::Green, 0)]);
+        assert_eq!(moved_tokens(&board.legal_moves(LudoColor::Red, LUDO_ENTRY_ROLL)), vec![0, 1]);
+        assert_eq!(board.legal_moves(LudoColor::Blue, LUDO_ENTRY_ROLL), LudoTurnOptions::Pass);
+    }
+
+    #[test]
+    fn entry_rolls_decide_who_leaves_the_yard() {
+        for (entry_rolls, leaving) in [(vec![LUDO_ENTRY_ROLL], false), (vec![1, LUDO_ENTRY_ROLL], true)] {
+            let board = LudoBoardState::with_rules(&[LudoColor::Red], LudoRuleSet { entry_rolls, ..LudoRuleSet::default() });
+            assert_eq!(board.resolve_move(LudoColor::Red, 0, 1).is_ok(), leaving);
+            assert!(board.resolve_move(LudoColor::Red, 0, LUDO_ENTRY_ROLL).is_ok());
+        }
+    }
+
+    #[test]
+    fn capture_on_safe_squares_toggles_the_protection() {
+        for capture_on_safe_squares in [false, true] {
+            let rules = LudoRuleSet { capture_on_safe_squares, ..LudoRuleSet::default() };
+            let board = board_with(&[LudoColor::Red, LudoColor::Green], rules, &[
+                (LudoColor::Red, 0, LudoTokenPosition::Track(5)),
+                (LudoColor::Green, 0, track_at(LudoColor::Green, 8)),
+            ]);
+            let captures = board.resolve_move(LudoColor::Red, 0, 3).unwrap().captures;
+            assert_eq!(captures.is_empty(), !capture_on_safe_squares);
+        }
+    }
+
+    #[test]
+    fn blockades_stop_other_colours() {
+        for blockades in [false, true] {
+            let rules = LudoRuleSet { blockades, ..LudoRuleSet::default() };
+            let board = board_with(&[LudoColor::Red, LudoColor::Green], rules, &[
+                (LudoColor::Red, 0, LudoTokenPosition::Track(1)),
+                (LudoColor::Red, 1, LudoTokenPosition::Track(10)),
+                (LudoColor::Red, 2, LudoTokenPosition::Track(10)),
+                (LudoColor::Green, 0, track_at(LudoColor::Green, 4)),
+                (LudoColor::Green, 1, track_at(LudoColor::Green, 4)),
+            ]);
+            /* passing and landing on the blockade, a blockade of the own colour never blocks. */
+            for roll in [3, 5] {
+                let resolved = board.resolve_move(LudoColor::Red, 0, roll);
+                if blockades {
+                    assert_eq!(resolved, Err(LudoRuleError::Blocked(4)));
+                } else {
+                    assert!(resolved.is_ok());
+                }
+            }
+            assert!(board.resolve_move(LudoColor::Red, 0, 2).is_ok());
+            assert_eq!(board.resolve_move(LudoColor::Green, 0, 6).is_ok(), !blockades);
+            assert!(board.is_blockade(4, LudoColor::Red));
+            assert!(!board.is_blockade(4, LudoColor::Green));
+        }
+    }
+
+    #[test]
+    fn bonus_roll_on_capture_grants_another_roll() {
+        for bonus_roll_on_capture in [false, true] {
+            let rules = LudoRuleSet { bonus_roll_on_capture, ..LudoRuleSet::default() };
+            let board = board_with(&[LudoColor::Red, LudoColor::Green], rules.clone(), &[
+                (LudoColor::Red, 0, LudoTokenPosition::Track(1)),
+                (LudoColor::Red, 1, LudoTokenPosition::Track(20)),
+                (LudoColor::Green, 0, track_at(LudoColor::Green, 4)),
+            ]);
+            assert_eq!(rules.grants_extra_roll(&board.resolve_move(LudoColor::Red, 0, 3).unwrap()), bonus_roll_on_capture);
+            assert!(!rules.grants_extra_roll(&board.resolve_move(LudoColor::Red, 1, 3).unwrap()));
+            assert!(rules.grants_extra_roll(&board.resolve_move(LudoColor::Red, 1, 6).unwrap()));
+        }
+    }
+
+    #[test]
+    fn bonus_roll_on_finish_grants_another_roll() {
+        for bonus_roll_on_finish in [false, true] {
+            let rules = LudoRuleSet { bonus_roll_on_finish, ..LudoRuleSet::default() };
+            let board = board_with(&[LudoColor::Red], rules.clone(), &[
+                (LudoColor::Red, 0, LudoTokenPosition::HomeColumn(2)),
+                (LudoColor::Red, 1, LudoTokenPosition::Track(20)),
+            ]);
+            assert_eq!(rules.grants_extra_roll(&board.resolve_move(LudoColor::Red, 0, 3).unwrap()), bonus_roll_on_finish);
+            assert!(!rules.grants_extra_roll(&board.resolve_move(LudoColor::Red, 1, 3).unwrap()));
+        }
+    }
+
+    #[test]
+    fn exact_roll_to_finish_toggles_the_overshoot() {
+        for exact_roll_to_finish in [false, true] {
+            let rules = LudoRuleSet { exact_roll_to_finish, ..LudoRuleSet::default() };
+            let board = board_with(&[LudoColor::Red], rules, &[(LudoColor::Red, 0, LudoTokenPosition::HomeColumn(3))]);
+            let resolved = board.resolve_move(LudoColor::Red, 0, 5);
+            if exact_roll_to_finish {
+                assert_eq!(resolved, Err(LudoRuleError::Overshoot(5)));
+            } else {
+                let ludo_move = resolved.unwrap();
+                assert_eq!((ludo_move.to, ludo_move.kind), (LudoTokenPosition::Finished, LudoMoveKind::Finish));
+            }
+        }
+    }
+
+    #[test]
+    fn tokens_per_player_sizes_the_yard() {
+        let rules = LudoRuleSet { tokens_per_player: 2, ..LudoRuleSet::default() };
+        let board = LudoBoardState::with_rules(&[LudoColor::Red], rules.clone());
+        assert_eq!(moved_tokens(&board.legal_moves(LudoColor::Red, LUDO_ENTRY_ROLL)), vec![0, 1]);
+        assert_eq!(board.resolve_move(LudoColor::Red, 2, LUDO_ENTRY_ROLL), Err(LudoRuleError::UnknownToken(2)));
+        assert!(rules.validate().is_ok());
+        assert!(LudoRuleSet { tokens_per_player: 0, ..LudoRuleSet::default() }.validate().is_err());
+        assert!(LudoRuleSet { entry_rolls: vec![7], ..LudoRuleSet::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+/* Persisted documents carry the version of their layout. Every older version keeps its own structs and is lifted to the
+   current one when read, documents written before versions existed are recognised by their shape. The data stays raw
+   json until its version is known, a detour over `serde_json::Value` would round the u128 points. */
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LudoVersioned<T> {
+    pub version: u32,
+    pub data: T,
+}
+
+pub struct LudoSchema<T> {
+    pub name: &'static str,
+    pub version: u32,
+    /* version of a document without a version header. */
+    pub legacy_version: fn(&str) -> Result<u32>,
+    /* reads the data of a supported version and migrates it to the current one. */
+    pub read: fn(u32, &str) -> Result<T>,
+}
+
+impl<T> LudoSchema<T> {
+    pub fn document<'a>(&self, data: &'a T) -> LudoVersioned<&'a T> {
+        LudoVersioned { version: self.version, data }
+    }
+
+    /* the data of a document at the current version, together with the version it was stored with if the document has
+       to be written again. Documents without a header are always rewritten. */
+    pub fn migrate(&self, document: &str) -> Result<(T, Option<u32>)> {
+        let (data, version, headed) = match serde_json::from_str::<LudoVersioned<&RawValue>>(document) {
+            Ok(versioned) => (versioned.data.get(), versioned.version, true),
+            Err(_) => (document, (self.legacy_version)(document)?, false),
+        };
+        if version == 0 || version > self.version {
+            return Err(anyhow!("{} version {} is not supported, this build reads up to version {}", self.name, version, self.version));
+        }
+        let data = (self.read)(version, data)?;
+        Ok((data, Some(version).filter(|version| !headed || *version < self.version)))
+    }
+}
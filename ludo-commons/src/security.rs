@@ -1,6 +1,109 @@
-pub const SECRET_KEY: [u8; 32] = [
-    0x3F, 0x5A, 0x92, 0xAB, 0x1C, 0x6D, 0x7E, 0x84,
-    0x9F, 0xB2, 0xC1, 0xE3, 0x4D, 0x76, 0xA9, 0xFA,
-    0x12, 0x45, 0x68, 0x7A, 0xBC, 0xDE, 0xFF, 0x01,
-    0x23, 0x56, 0x78, 0x90, 0xAB, 0xCD, 0xEF, 0x34
-];
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+/* file of the netcode private key, shared only by the server and the token issuer. */
+pub const LUDO_PRIVATE_KEY_FILE: &str = "server.key";
+/* file of the secret shared by the server and its clients to answer the handshake challenge. */
+pub const LUDO_HANDSHAKE_KEY_FILE: &str = "handshake.key";
+/* file of the connect token minted for the profile of this client. */
+pub const LUDO_CONNECT_TOKEN_FILE: &str = "connect-token.bin";
+
+pub const LUDO_KEY_BYTES: usize = 32;
+/* size of the netcode user data carried inside a connect token. */
+pub const LUDO_USER_DATA_BYTES: usize = 256;
+
+pub type LudoKey = [u8; LUDO_KEY_BYTES];
+pub type LudoNonce = [u8; 32];
+pub type LudoHandshakeResponse = [u8; 32];
+pub type LudoProfileSignature = Signature;
+
+type LudoHmac = Hmac<Sha256>;
+
+pub fn generate_key() -> LudoKey {
+    let mut key = [0u8; LUDO_KEY_BYTES];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
+
+pub fn load_key(file: &str) -> Result<LudoKey> {
+    let mut open_options = OpenOptions::new().read(true).open(PathBuf::from(file))?;
+    let mut buf = Vec::new();
+    open_options.read_to_end(&mut buf)?;
+    buf.try_into().map_err(|buf: Vec<u8>| anyhow!("key {} has {} bytes, expected {}", file, buf.len(), LUDO_KEY_BYTES))
+}
+
+/* loads the key of the file, a missing file is created with a fresh key. */
+pub fn load_or_generate_key(file: &str) -> Result<LudoKey> {
+    if PathBuf::from(file).exists() {
+        load_key(file)
+    } else {
+        let key = generate_key();
+        write_key(file, &key)?;
+        Ok(key)
+    }
+}
+
+pub fn write_key(file: &str, key: &LudoKey) -> Result<()> {
+    let mut open_options = OpenOptions::new().write(true).create(true).truncate(true).open(PathBuf::from(file))?;
+    open_options.write_all(key)?;
+    Ok(())
+}
+
+pub fn generate_nonce() -> LudoNonce {
+    let mut nonce = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/* HMAC-SHA256 of the nonce, proves the knowledge of the key without sending it. */
+pub fn handshake_response(key: &LudoKey, nonce: &LudoNonce) -> LudoHandshakeResponse {
+    let mut mac = LudoHmac::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(nonce);
+    mac.finalize().into_bytes().into()
+}
+
+/* compares in constant time. */
+pub fn verify_handshake_response(key: &LudoKey, nonce: &LudoNonce, response: &LudoHandshakeResponse) -> bool {
+    let mut mac = LudoHmac::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(nonce);
+    mac.verify_slice(response).is_ok()
+}
+
+fn profile_message(nonce: &LudoNonce, unique_id: &[u8; 16]) -> Vec<u8> {
+    [b"ludo-profile".as_slice(), nonce, unique_id].concat()
+}
+
+pub fn profile_public_key(secret_key: &LudoKey) -> [u8; 32] {
+    SigningKey::from_bytes(secret_key).verifying_key().to_bytes()
+}
+
+/* signs the unique id together with the handshake nonce, so a signature cannot be replayed in another session. */
+pub fn sign_profile(secret_key: &LudoKey, nonce: &LudoNonce, unique_id: &[u8; 16]) -> LudoProfileSignature {
+    SigningKey::from_bytes(secret_key).sign(&profile_message(nonce, unique_id))
+}
+
+pub fn verify_profile(public_key: &[u8; 32], nonce: &LudoNonce, unique_id: &[u8; 16], signature: &LudoProfileSignature) -> bool {
+    match VerifyingKey::from_bytes(public_key) {
+        Ok(verifying_key) => verifying_key.verify_strict(&profile_message(nonce, unique_id), signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/* binds a profile to a connect token, the server reads it back from the transport. */
+pub fn profile_user_data(unique_id: &[u8; 16]) -> [u8; LUDO_USER_DATA_BYTES] {
+    let mut user_data = [0u8; LUDO_USER_DATA_BYTES];
+    user_data[..16].copy_from_slice(unique_id);
+    user_data
+}
+
+pub fn profile_id_of_user_data(user_data: &[u8; LUDO_USER_DATA_BYTES]) -> [u8; 16] {
+    let mut unique_id = [0u8; 16];
+    unique_id.copy_from_slice(&user_data[..16]);
+    unique_id
+}
@@ -0,0 +1,84 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+
+/* Crash safe files. A value is written next to its file first and renamed over it afterwards, the replaced file stays
+   behind as the last good copy. The checksum covers the exact bytes of the payload. */
+
+#[derive(Serialize, Deserialize)]
+struct LudoStorageEnvelope<'a> {
+    checksum: String,
+    #[serde(borrow)]
+    data: &'a RawValue,
+}
+
+pub fn backup_path(file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.bak", file))
+}
+
+fn temporary_path(file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.tmp", file))
+}
+
+pub fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/* the payload of a file after its checksum was verified, files written before the storage layer have no envelope. */
+fn read_payload(path: &Path) -> Result<String> {
+    let mut buf = String::new();
+    OpenOptions::new().read(true).open(path)?.read_to_string(&mut buf)?;
+    match serde_json::from_str::<LudoStorageEnvelope>(&buf) {
+        Ok(envelope) if envelope.checksum == checksum(envelope.data.get().as_bytes()) => Ok(envelope.data.get().to_string()),
+        Ok(_) => Err(anyhow!("checksum of {} does not match its content", path.display())),
+        Err(_) => {
+            serde_json::from_str::<serde::de::IgnoredAny>(&buf).map_err(|error| anyhow!("{} is neither a storage file nor plain json: {}", path.display(), error))?;
+            Ok(buf)
+        }
+    }
+}
+
+/* the value of the file, or of its last good copy if the file is missing or damaged. None if neither exists. */
+pub fn read<T>(file: &str) -> Result<Option<T>> where T: DeserializeOwned {
+    read_with(file, |payload| Ok(serde_json::from_str::<T>(payload)?))
+}
+
+/* like `read`, with the payload parsed by the caller. Only a file that can not be read or fails its checksum is
+   recovered, a payload the caller rejects is returned as error. A document of a newer build must never be replaced by
+   an older copy. */
+pub fn read_with<T>(file: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Option<T>> {
+    let path = PathBuf::from(file);
+    let backup = backup_path(file);
+    match read_payload(&path) {
+        Ok(payload) => parse(&payload).map(Some),
+        Err(_) if !path.exists() && !backup.exists() => Ok(None),
+        Err(error) if backup.exists() => {
+            bevy::log::warn!("unable to read {}: {}, recovering from {}", file, error, backup.display());
+            parse(&read_payload(&backup)?).map(Some)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+pub fn write<T>(file: &str, value: &T) -> Result<()> where T: Serialize {
+    let data = serde_json::to_string(value)?;
+    let checksum = checksum(data.as_bytes());
+    let envelope = serde_json::to_string(&LudoStorageEnvelope { checksum, data: &RawValue::from_string(data)? })?;
+    let temporary = temporary_path(file);
+    let mut open_options = OpenOptions::new().write(true).create(true).truncate(true).open(&temporary)?;
+    open_options.write_all(envelope.as_bytes())?;
+    open_options.sync_all()?;
+    drop(open_options);
+    let path = PathBuf::from(file);
+    /* a damaged file never replaces the last good copy. */
+    if path.exists() && read_payload(&path).is_ok() {
+        fs::rename(&path, backup_path(file))?;
+    }
+    fs::rename(&temporary, &path)?;
+    Ok(())
+}
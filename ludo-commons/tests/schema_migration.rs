@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use ludo_commons::game::{LudoGameProfile, LudoGameProfileData, LudoGameStatistics, LUDO_LOCAL_PROFILE_VERSION, LUDO_PROFILES_VERSION};
+use ludo_commons::{security, storage};
+
+const FIRST_ID: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+const SECOND_ID: [u8; 16] = [16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1];
+
+/* a private copy of a fixture, tests run in parallel and loading may rewrite the file. */
+fn copy_fixture(fixture: &str, test: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ludo-{}-{}-{}", std::process::id(), test, fixture));
+    remove(&path);
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixture), &path).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+fn remove(path: &PathBuf) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(storage::backup_path(&path.to_string_lossy()));
+}
+
+fn stored_version(file: &str) -> u32 {
+    storage::read::<Value>(file).unwrap().unwrap().get("version").and_then(Value::as_u64).unwrap() as u32
+}
+
+fn load_profiles(fixture: &str, test: &str) -> (Vec<LudoGameProfileData>, String) {
+    let file = copy_fixture(fixture, test);
+    let mut list = LudoGameProfileData::load_from_file(&file).unwrap();
+    list.sort_by_key(|profile| profile.unique_id);
+    (list, file)
+}
+
+#[test]
+fn profiles_of_version_1_have_no_key() {
+    let (list, file) = load_profiles("profiles-v1.json", "v1");
+    assert_eq!(list.len(), 2);
+    assert_eq!((list[0].unique_id, list[0].points, list[0].public_key), (FIRST_ID, 150, None));
+    assert_eq!((list[1].unique_id, list[1].points, list[1].public_key), (SECOND_ID, 25, None));
+    assert_eq!(list[0].statistics, LudoGameStatistics::default());
+    assert_eq!(stored_version(&file), LUDO_PROFILES_VERSION);
+    remove(&PathBuf::from(file));
+}
+
+#[test]
+fn profiles_of_version_2_keep_their_keys() {
+    for (fixture, test) in [("profiles-v2.json", "v2"), ("profiles-v2-checksummed.json", "v2-checksummed"), ("profiles-v2-versioned.json", "v2-versioned")] {
+        let (list, file) = load_profiles(fixture, test);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].public_key, Some([7; 32]));
+        assert_eq!(list[1].public_key, None);
+        assert_eq!(list[1].points, 25);
+        assert_eq!(list[0].statistics, LudoGameStatistics::default());
+        assert_eq!(stored_version(&file), LUDO_PROFILES_VERSION);
+        remove(&PathBuf::from(file));
+    }
+}
+
+#[test]
+fn current_profiles_are_not_rewritten() {
+    let (list, file) = load_profiles("profiles-v3-versioned.json", "current");
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0].public_key, Some([7; 32]));
+    assert_eq!((list[0].statistics.wins, list[0].statistics.captures, list[0].statistics.history.len()), (1, 3, 1));
+    assert_eq!(list[1].statistics.games, 0);
+    assert!(!storage::backup_path(&file).exists());
+    remove(&PathBuf::from(file));
+}
+
+/* the files of a path, the newer document and the last good copy it replaced. */
+fn contents(path: &PathBuf) -> (Vec<u8>, Vec<u8>) {
+    (fs::read(path).unwrap(), fs::read(storage::backup_path(&path.to_string_lossy())).unwrap())
+}
+
+#[test]
+fn profiles_of_a_newer_version_are_rejected() {
+    let path = std::env::temp_dir().join(format!("ludo-{}-newer-profiles.json", std::process::id()));
+    remove(&path);
+    let file = path.to_string_lossy().to_string();
+    LudoGameProfileData::load_to_file(&file, vec![]).unwrap();
+    storage::write(&file, &serde_json::json!({ "version": LUDO_PROFILES_VERSION + 1, "data": [] })).unwrap();
+    let before = contents(&path);
+    assert!(LudoGameProfileData::load_from_file(&file).is_err());
+    assert_eq!(contents(&path), before);
+    remove(&path);
+}
+
+#[test]
+fn game_profile_of_a_newer_version_is_rejected() {
+    let path = std::env::temp_dir().join(format!("ludo-{}-newer-game-profile.json", std::process::id()));
+    remove(&path);
+    let file = path.to_string_lossy().to_string();
+    LudoGameProfile::load_from_file(&file).unwrap();
+    storage::write(&file, &serde_json::json!({ "version": LUDO_LOCAL_PROFILE_VERSION + 1, "data": {} })).unwrap();
+    let before = contents(&path);
+    assert!(LudoGameProfile::load_from_file(&file).is_err());
+    assert_eq!(contents(&path), before);
+    remove(&path);
+}
+
+#[test]
+fn game_profile_of_version_1_gets_a_keypair() {
+    let file = copy_fixture("game-profile-v1.json", "v1");
+    let (profile, key) = LudoGameProfile::load_from_file(&file).unwrap();
+    assert_eq!((profile.unique_id, profile.nickname.as_str(), profile.age), (FIRST_ID, "alice", 17));
+    assert_eq!(profile.public_key, security::profile_public_key(&key.0));
+    assert_eq!(stored_version(&file), LUDO_LOCAL_PROFILE_VERSION);
+    let (reloaded, reloaded_key) = LudoGameProfile::load_from_file(&file).unwrap();
+    assert_eq!((reloaded.public_key, reloaded_key.0), (profile.public_key, key.0));
+    remove(&PathBuf::from(file));
+}
+
+#[test]
+fn game_profile_is_read_without_a_rewrite() {
+    for fixture in ["game-profile-v1.json", "game-profile-v2.json", "game-profile-v2-versioned.json"] {
+        let file = copy_fixture(fixture, "read");
+        let before = fs::read_to_string(&file).unwrap();
+        let profile = LudoGameProfile::read_from_file(&file).unwrap();
+        assert_eq!((profile.unique_id, profile.nickname.as_str()), (FIRST_ID, "alice"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), before);
+        remove(&PathBuf::from(file));
+    }
+    assert!(LudoGameProfile::read_from_file(&std::env::temp_dir().join("ludo-no-such-game-profile.json").to_string_lossy()).is_err());
+}
+
+#[test]
+fn game_profile_of_version_2_keeps_its_keypair() {
+    for (fixture, test) in [("game-profile-v2.json", "v2"), ("game-profile-v2-versioned.json", "current")] {
+        let file = copy_fixture(fixture, test);
+        let (profile, key) = LudoGameProfile::load_from_file(&file).unwrap();
+        assert_eq!((profile.unique_id, profile.nickname.as_str()), (FIRST_ID, "alice"));
+        assert_eq!((profile.public_key, key.0), ([3; 32], [9; 32]));
+        assert_eq!(stored_version(&file), LUDO_LOCAL_PROFILE_VERSION);
+        remove(&PathBuf::from(file));
+    }
+}
+
+#[test]
+fn profiles_keep_points_beyond_u64() {
+    let path = std::env::temp_dir().join(format!("ludo-{}-large-profiles.json", std::process::id()));
+    remove(&path);
+    let file = path.to_string_lossy().to_string();
+    LudoGameProfileData::load_to_file(&file, vec![LudoGameProfileData { unique_id: FIRST_ID, points: u128::MAX, public_key: None, statistics: LudoGameStatistics::default() }]).unwrap();
+    assert_eq!(LudoGameProfileData::load_from_file(&file).unwrap()[0].points, u128::MAX);
+    remove(&path);
+}
+
+#[test]
+fn damaged_game_profile_keeps_its_keypair() {
+    let path = std::env::temp_dir().join(format!("ludo-{}-damaged-game-profile.json", std::process::id()));
+    remove(&path);
+    let file = path.to_string_lossy().to_string();
+    let (profile, key) = LudoGameProfile::load_from_file(&file).unwrap();
+    LudoGameProfile::load_to_file(&file, profile.clone(), &key).unwrap();
+    fs::write(&path, "{\"checksum\":\"00\",\"data\":{}}").unwrap();
+    let (recovered, recovered_key) = LudoGameProfile::load_from_file(&file).unwrap();
+    assert_eq!((recovered.unique_id, recovered_key.0), (profile.unique_id, key.0));
+    remove(&path);
+}
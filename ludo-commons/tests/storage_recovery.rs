@@ -0,0 +1,88 @@
+use std::fs;
+use std::path::PathBuf;
+use ludo_commons::storage;
+
+/* a fresh file per test, tests run in parallel. */
+fn fresh_file(test: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ludo-{}-storage-{}.json", std::process::id(), test));
+    remove(&path.to_string_lossy());
+    path.to_string_lossy().to_string()
+}
+
+fn remove(file: &str) {
+    let _ = fs::remove_file(file);
+    let _ = fs::remove_file(storage::backup_path(file));
+}
+
+/* changes the payload but keeps the checksum of the old one. */
+fn tamper(file: &str) {
+    let content = fs::read_to_string(file).unwrap();
+    fs::write(file, content.replace("\"first\"", "\"forged\"").replace("\"second\"", "\"forged\"")).unwrap();
+}
+
+#[test]
+fn missing_files_read_as_none() {
+    let file = fresh_file("missing");
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), None);
+}
+
+#[test]
+fn values_round_trip() {
+    let file = fresh_file("round-trip");
+    storage::write(&file, &vec!["first".to_string()]).unwrap();
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["first".to_string()]));
+    assert!(!storage::backup_path(&file).exists());
+    storage::write(&file, &vec!["second".to_string()]).unwrap();
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["second".to_string()]));
+    assert_eq!(storage::read::<Vec<String>>(&storage::backup_path(&file).to_string_lossy()).unwrap(), Some(vec!["first".to_string()]));
+    remove(&file);
+}
+
+#[test]
+fn checksum_mismatch_falls_back_to_the_last_good_copy() {
+    let file = fresh_file("checksum-backup");
+    storage::write(&file, &vec!["first".to_string()]).unwrap();
+    storage::write(&file, &vec!["second".to_string()]).unwrap();
+    tamper(&file);
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["first".to_string()]));
+    remove(&file);
+}
+
+#[test]
+fn checksum_mismatch_without_a_copy_is_an_error() {
+    let file = fresh_file("checksum");
+    storage::write(&file, &vec!["first".to_string()]).unwrap();
+    tamper(&file);
+    assert!(storage::read::<Vec<String>>(&file).is_err());
+    remove(&file);
+}
+
+#[test]
+fn a_missing_file_is_recovered_from_its_copy() {
+    let file = fresh_file("missing-backup");
+    storage::write(&file, &vec!["first".to_string()]).unwrap();
+    storage::write(&file, &vec!["second".to_string()]).unwrap();
+    fs::remove_file(PathBuf::from(&file)).unwrap();
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["first".to_string()]));
+    remove(&file);
+}
+
+#[test]
+fn a_damaged_file_never_replaces_the_last_good_copy() {
+    let file = fresh_file("damaged-write");
+    storage::write(&file, &vec!["first".to_string()]).unwrap();
+    storage::write(&file, &vec!["second".to_string()]).unwrap();
+    fs::write(&file, "{ truncated").unwrap();
+    storage::write(&file, &vec!["third".to_string()]).unwrap();
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["third".to_string()]));
+    assert_eq!(storage::read::<Vec<String>>(&storage::backup_path(&file).to_string_lossy()).unwrap(), Some(vec!["first".to_string()]));
+    remove(&file);
+}
+
+#[test]
+fn plain_json_of_older_versions_is_read() {
+    let file = fresh_file("plain");
+    fs::write(&file, "[\"first\"]").unwrap();
+    assert_eq!(storage::read::<Vec<String>>(&file).unwrap(), Some(vec!["first".to_string()]));
+    remove(&file);
+}
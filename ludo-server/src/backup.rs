@@ -1,16 +1,140 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
 use bevy::prelude::*;
-use ludo_commons::game::LudoGameProfileData;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use ludo_commons::game::{LudoGameProfileData, LUDO_PROFILES_SCHEMA};
+use ludo_commons::storage;
+use crate::configuration::LudoServerConfiguration;
+use crate::store::LudoProfiles;
+
+const LUDO_SNAPSHOT_NAME_FORMAT: &str = "profiles-%Y%m%d-%H%M%S%.3f.json";
 
 #[derive(Component)]
 pub struct LudoBackupProfileTimer(pub Timer);
 
-pub fn handle_backup_profile_timer(time: Res<Time>, mut timer: Query<&mut LudoBackupProfileTimer>, profiles: Query<&LudoGameProfileData>) {
-    if timer.get_single_mut().unwrap().0.tick(time.delta()).just_finished() {
-        let mut list = vec![];
-        profiles.iter().for_each(|profile| {
-            list.push(profile.clone());
-        });
-        LudoGameProfileData::load_to_file("profiles.json", list.clone()).expect("unable to save ludo game profiles!");
-        info!("Backup of game profiles saved. Size: [{}]!", list.len());
+#[derive(Component)]
+pub struct LudoSnapshotTimer(pub Timer);
+
+/* snapshots are documents of the profiles schema, the time they were taken is part of their file name. A snapshot of a
+   newer server is never restored. */
+pub fn read_snapshot(document: &str) -> Result<Vec<LudoGameProfileData>> {
+    let (profiles, _) = LUDO_PROFILES_SCHEMA.migrate(document)?;
+    validate_snapshot(&profiles)?;
+    Ok(profiles)
+}
+
+fn validate_snapshot(profiles: &[LudoGameProfileData]) -> Result<()> {
+    let mut unique_ids = HashSet::new();
+    if let Some(profile) = profiles.iter().find(|profile| !unique_ids.insert(profile.unique_id)) {
+        return Err(anyhow!("snapshot contains the profile {:?} twice", profile.unique_id));
+    }
+    Ok(())
+}
+
+/* snapshot files of the directory with the time they were taken, newest first. */
+pub fn list_snapshots(directory: &str) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+    if !Path::new(directory).exists() {
+        return Ok(vec![]);
+    }
+    let mut snapshots = fs::read_dir(directory)?.filter_map(|entry| entry.ok()).filter_map(|entry| {
+        let created_at = NaiveDateTime::parse_from_str(&entry.file_name().to_string_lossy(), LUDO_SNAPSHOT_NAME_FORMAT).ok()?;
+        Some((created_at, entry.path()))
+    }).collect::<Vec<(NaiveDateTime, PathBuf)>>();
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.0));
+    Ok(snapshots)
+}
+
+pub fn take_snapshot(profiles: &LudoProfiles, configuration: &LudoServerConfiguration) -> Result<PathBuf> {
+    let created_at = Utc::now().naive_utc();
+    let list = profiles.0.all()?;
+    fs::create_dir_all(&configuration.backup_directory)?;
+    let path = Path::new(&configuration.backup_directory).join(created_at.format(LUDO_SNAPSHOT_NAME_FORMAT).to_string());
+    storage::write(&path.to_string_lossy(), &LUDO_PROFILES_SCHEMA.document(&list))?;
+    Ok(path)
+}
+
+/* keeps the newest snapshots and the newest snapshot of each of the last days, everything else is deleted. */
+pub fn prune_snapshots(configuration: &LudoServerConfiguration) -> Result<usize> {
+    let snapshots = list_snapshots(&configuration.backup_directory)?;
+    let mut days = HashSet::<NaiveDate>::new();
+    let mut removed = 0;
+    for (index, (created_at, path)) in snapshots.iter().enumerate() {
+        let newest_of_day = days.len() < configuration.backup_keep_daily && days.insert(created_at.date());
+        if index < configuration.backup_keep_last || newest_of_day {
+            continue;
+        }
+        fs::remove_file(path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/* validates a snapshot, given by path or by its name in the backup directory, and replaces every stored profile with it.
+   The current profiles are snapshotted first so a restore can be undone. */
+pub fn restore(configuration: &LudoServerConfiguration, snapshot: &str) -> Result<usize> {
+    let path = if Path::new(snapshot).exists() { PathBuf::from(snapshot) } else { Path::new(&configuration.backup_directory).join(snapshot) };
+    if !path.exists() {
+        return Err(anyhow!("snapshot {} does not exist", path.display()));
+    }
+    let snapshot = storage::read_with(&path.to_string_lossy(), read_snapshot)?.ok_or(anyhow!("snapshot {} is empty", path.display()))?;
+    let mut profiles = LudoProfiles::open(configuration)?;
+    let previous = take_snapshot(&profiles, configuration)?;
+    info!("saved the current profiles to {} before restoring.", previous.display());
+    profiles.0.replace_all(&snapshot)?;
+    profiles.0.flush()?;
+    info!("restored {} profiles of the snapshot {}.", snapshot.len(), path.display());
+    Ok(snapshot.len())
+}
+
+pub fn handle_backup_profile_timer(time: Res<Time>, mut timer: Query<&mut LudoBackupProfileTimer>, mut profiles: ResMut<LudoProfiles>) {
+    let Ok(mut timer) = timer.get_single_mut() else { return };
+    if timer.0.tick(time.delta()).just_finished() {
+        match profiles.0.flush() {
+            Ok(()) => info!("Backup of game profiles saved!"),
+            /* the last good copy stays untouched, the next tick tries again. */
+            Err(error) => error!("unable to save ludo game profiles: {}", error),
+        }
+    }
+}
+
+pub fn handle_snapshot_timer(time: Res<Time>, mut timer: Query<&mut LudoSnapshotTimer>, profiles: Res<LudoProfiles>, configuration: Res<LudoServerConfiguration>) {
+    let Ok(mut timer) = timer.get_single_mut() else { return };
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    match take_snapshot(&profiles, &configuration) {
+        Ok(path) => info!("snapshot of game profiles saved to {}.", path.display()),
+        Err(error) => error!("unable to take a snapshot of the ludo game profiles: {}", error),
+    }
+    match prune_snapshots(&configuration) {
+        Ok(0) => {}
+        Ok(removed) => info!("removed {} old snapshots.", removed),
+        Err(error) => error!("unable to remove old snapshots: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ludo_commons::game::LUDO_PROFILES_VERSION;
+    use super::*;
+
+    const PROFILE: &str = r#"{"unique_id":[1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1],"points":340282366920938463463374607431768211455,"public_key":null}"#;
+
+    fn snapshot(version: u32, profiles: &[&str]) -> String {
+        format!(r#"{{"version":{},"data":[{}]}}"#, version, profiles.join(","))
+    }
+
+    #[test]
+    fn snapshots_are_profile_documents() {
+        let profiles = read_snapshot(&snapshot(LUDO_PROFILES_VERSION, &[PROFILE])).unwrap();
+        assert_eq!((profiles[0].unique_id, profiles[0].points), ([1; 16], u128::MAX));
+    }
+
+    #[test]
+    fn unsupported_snapshots_are_rejected() {
+        assert!(read_snapshot(&snapshot(LUDO_PROFILES_VERSION + 1, &[])).is_err());
+        assert!(read_snapshot(&snapshot(LUDO_PROFILES_VERSION, &[PROFILE, PROFILE])).is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use ludo_commons::bot;
+use ludo_commons::game::LudoGameState;
+use crate::dice::LudoDiceService;
+use crate::game::{self, LudoBotTimer, LudoSeatOccupant, LudoServerMatch, LudoTurnPhase};
+use crate::room::LudoRoom;
+use crate::server::LudoOnlineClientPool;
+
+/* lets the bot on the active seat roll or move, one action per tick of the bot timer. */
+pub fn handle_bot_turn_system(
+    time: Res<Time>,
+    mut rooms: Query<(&LudoRoom, &mut LudoServerMatch, &mut LudoDiceService, &mut LudoBotTimer)>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for (room, mut ludo_match, mut dice, mut timer) in rooms.iter_mut() {
+        if !timer.0.tick(time.delta()).just_finished() || room.state != LudoGameState::InGame || !ludo_match.has_present_client(room) {
+            continue;
+        }
+        let LudoSeatOccupant::Bot(difficulty) = ludo_match.active().occupant else { continue };
+        match ludo_match.phase.clone() {
+            LudoTurnPhase::AwaitingRoll => game::roll_active(room, &mut ludo_match, &mut dice, &pool, &mut server),
+            LudoTurnPhase::AwaitingMove(_, moves) => {
+                let Some(chosen) = bot::choose_move(&ludo_match.board, &moves, difficulty) else { continue };
+                match ludo_match.play(chosen.token) {
+                    Ok(ludo_move) => {
+                        info!("{:?} bot moved {:?} token {} to {:?}.", difficulty, ludo_move.player, ludo_move.token, ludo_move.to);
+                        ludo_match.broadcast(room, &pool, &mut server);
+                    }
+                    Err(error) => warn!("{:?} bot chose an illegal move: {}", difficulty, error),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use bevy::prelude::Resource;
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use ludo_commons::bot::LudoBotDifficulty;
+use ludo_commons::dice::LudoDiceSeed;
+use ludo_commons::rules::LudoColor;
+use ludo_commons::security;
+use crate::dice::LudoDiceConfiguration;
+use crate::store::LudoProfileStoreKind;
+
+/// Authoritative ludo server.
+#[derive(Parser, Debug)]
+#[command(name = "ludo-server", version)]
+pub struct LudoServerArguments {
+    /// Configuration file, created with the defaults if missing.
+    #[arg(long, default_value = "server.toml")]
+    pub config: String,
+    #[arg(long)]
+    pub address: Option<SocketAddr>,
+    #[arg(long)]
+    pub max_clients: Option<usize>,
+    #[arg(long)]
+    pub min_players: Option<u8>,
+    #[arg(long)]
+    pub max_players: Option<u8>,
+    #[arg(long)]
+    pub profiles_file: Option<String>,
+    #[arg(long)]
+    pub rules_file: Option<String>,
+    #[arg(long)]
+    pub dice_commit_reveal: Option<bool>,
+    /// Hex encoded seed every match rolls its dice with.
+    #[arg(long)]
+    pub dice_seed: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<LudoServerCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LudoServerCommand {
+    /// Replaces every stored profile with a snapshot, the server must not be running.
+    Restore {
+        /// Snapshot file, or its name in the backup directory.
+        snapshot: String,
+    },
+}
+
+/* everything an operator may change without a rebuild, loaded before the server plugin builds. */
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LudoServerConfiguration {
+    pub address: SocketAddr,
+    /* addresses written into connect tokens, the bind address if empty. */
+    pub public_addresses: Vec<SocketAddr>,
+    pub max_clients: usize,
+    pub max_rooms: usize,
+    pub max_spectators_per_room: usize,
+    /* defaults of a room created without its own limits. */
+    pub min_players_to_start: u8,
+    pub max_players_to_start: u8,
+    /* how long a room with enough players waits for more before its match starts, a full room starts right away. */
+    pub room_start_delay_ms: u64,
+    pub handshake_timeout_ms: u64,
+    /* how long an address with too many failed handshakes is refused. */
+    pub handshake_block_ms: u64,
+    pub backup_interval_ms: u64,
+    pub snapshot_interval_ms: u64,
+    pub profiles_broadcast_interval_ms: u64,
+    /* after this wait a quick play entry accepts matches with fewer players than asked for. */
+    pub matchmaking_timeout_ms: u64,
+    /* seats quick play players with similar profile points together. */
+    pub matchmaking_balance: bool,
+    /* how long the seat of a disconnected player stays reserved in a running match. */
+    pub reconnect_grace_ms: u64,
+    pub bot_difficulty: LudoBotDifficulty,
+    /* quick play matches formed after the timeout get bots for the seats nobody queued for. */
+    pub bot_fill_quick_play: bool,
+    /* a bot takes over the seat of a player whose reconnect grace period expired. */
+    pub bot_replace_dropped: bool,
+    /* pause between two actions of a bot, so players can follow. */
+    pub bot_delay_ms: u64,
+    /* time for every roll and move before the server acts for the player. */
+    pub turn_timeout_ms: u64,
+    /* turn timeouts in a row after which a player is kicked, zero never kicks. */
+    pub turn_timeouts_to_kick: u8,
+    /* how long the results stay on screen before a room waits for its next match. */
+    pub closing_duration_ms: u64,
+    /* publish the hash of the dice seed at match start and the seed itself at match end. */
+    pub dice_commit_reveal: bool,
+    /* hex encoded seed for reproducible matches, a fresh one is drawn per match if missing. */
+    pub dice_seed: Option<String>,
+    /* profile points awarded per finishing place, first place first. */
+    pub placement_points: Vec<u64>,
+    /* where profiles live, the profiles file or the profiles database. */
+    pub profile_store: LudoProfileStoreKind,
+    pub profiles_file: String,
+    pub profiles_database: String,
+    pub backup_directory: String,
+    /* the newest snapshots are always kept, older ones only as the newest snapshot of one of the last days. */
+    pub backup_keep_last: usize,
+    pub backup_keep_daily: usize,
+    pub rules_file: String,
+    pub private_key_file: String,
+    pub handshake_key_file: String,
+}
+
+impl Default for LudoServerConfiguration {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:2000".parse().unwrap(),
+            public_addresses: vec![],
+            max_clients: 6,
+            max_rooms: 16,
+            max_spectators_per_room: 8,
+            min_players_to_start: 1,
+            max_players_to_start: 4,
+            room_start_delay_ms: 15000,
+            handshake_timeout_ms: 500,
+            handshake_block_ms: 300000,
+            backup_interval_ms: 9000,
+            snapshot_interval_ms: 3600000,
+            profiles_broadcast_interval_ms: 2000,
+            matchmaking_timeout_ms: 20000,
+            matchmaking_balance: true,
+            reconnect_grace_ms: 60000,
+            bot_difficulty: LudoBotDifficulty::Greedy,
+            bot_fill_quick_play: true,
+            bot_replace_dropped: true,
+            bot_delay_ms: 800,
+            turn_timeout_ms: 30000,
+            turn_timeouts_to_kick: 3,
+            closing_duration_ms: 5000,
+            dice_commit_reveal: true,
+            dice_seed: None,
+            placement_points: vec![100, 50, 25, 0],
+            profile_store: LudoProfileStoreKind::Json,
+            profiles_file: "profiles.json".to_string(),
+            profiles_database: "profiles.sqlite".to_string(),
+            backup_directory: "backups".to_string(),
+            backup_keep_last: 24,
+            backup_keep_daily: 7,
+            rules_file: "rules.json".to_string(),
+            private_key_file: security::LUDO_PRIVATE_KEY_FILE.to_string(),
+            handshake_key_file: security::LUDO_HANDSHAKE_KEY_FILE.to_string(),
+        }
+    }
+}
+
+impl LudoServerConfiguration {
+    /* reads the file of the arguments, applies their overrides and validates the result. */
+    pub fn load(arguments: &LudoServerArguments) -> Result<LudoServerConfiguration> {
+        let mut configuration = Self::load_from_file(&arguments.config)?;
+        if let Some(address) = arguments.address {
+            configuration.address = address;
+        }
+        if let Some(max_clients) = arguments.max_clients {
+            configuration.max_clients = max_clients;
+        }
+        if let Some(min_players) = arguments.min_players {
+            configuration.min_players_to_start = min_players;
+        }
+        if let Some(max_players) = arguments.max_players {
+            configuration.max_players_to_start = max_players;
+        }
+        if let Some(profiles_file) = &arguments.profiles_file {
+            configuration.profiles_file = profiles_file.clone();
+        }
+        if let Some(rules_file) = &arguments.rules_file {
+            configuration.rules_file = rules_file.clone();
+        }
+        if let Some(dice_commit_reveal) = arguments.dice_commit_reveal {
+            configuration.dice_commit_reveal = dice_commit_reveal;
+        }
+        if let Some(dice_seed) = &arguments.dice_seed {
+            configuration.dice_seed = Some(dice_seed.clone());
+        }
+        configuration.validate()?;
+        Ok(configuration)
+    }
+
+    pub fn load_from_file(file: &str) -> Result<LudoServerConfiguration> {
+        let file_path = PathBuf::from(file);
+        if file_path.exists() {
+            let mut open_options = OpenOptions::new().read(true).open(file_path)?;
+            let mut buf = String::new();
+            open_options.read_to_string(&mut buf)?;
+            Ok(toml::from_str::<LudoServerConfiguration>(&buf)?)
+        } else {
+            let mut open_options = OpenOptions::new().write(true).create(true).truncate(true).open(file_path)?;
+            open_options.write_all(toml::to_string_pretty(&LudoServerConfiguration::default())?.as_bytes())?;
+            Ok(LudoServerConfiguration::default())
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.min_players_to_start < 1 || self.min_players_to_start > self.max_players_to_start || self.max_players_to_start as usize > LudoColor::ALL.len() {
+            return Err(anyhow!("players to start must satisfy 1 <= min ({}) <= max ({}) <= {}", self.min_players_to_start, self.max_players_to_start, LudoColor::ALL.len()));
+        }
+        if self.max_clients < self.max_players_to_start as usize {
+            return Err(anyhow!("max clients ({}) can not be less than the max players to start ({})", self.max_clients, self.max_players_to_start));
+        }
+        if self.max_rooms == 0 {
+            return Err(anyhow!("the server has to host at least one room"));
+        }
+        if self.handshake_timeout_ms == 0 || self.handshake_block_ms == 0 || self.backup_interval_ms == 0 || self.snapshot_interval_ms == 0 || self.profiles_broadcast_interval_ms == 0 || self.matchmaking_timeout_ms == 0 || self.reconnect_grace_ms == 0 || self.bot_delay_ms == 0 || self.turn_timeout_ms == 0 || self.closing_duration_ms == 0 {
+            return Err(anyhow!("timeouts and intervals must be greater than zero"));
+        }
+        if self.placement_points.len() < LudoColor::ALL.len() {
+            return Err(anyhow!("placement points need a value for each of the {} places: {:?}", LudoColor::ALL.len(), self.placement_points));
+        }
+        self.dice_seed()?;
+        Ok(())
+    }
+
+    pub fn dice_seed(&self) -> Result<Option<LudoDiceSeed>> {
+        let Some(seed) = &self.dice_seed else { return Ok(None) };
+        if seed.len() != 2 * size_of::<LudoDiceSeed>() || !seed.chars().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(anyhow!("the dice seed must be {} hex digits: {}", 2 * size_of::<LudoDiceSeed>(), seed));
+        }
+        let mut bytes = [0u8; size_of::<LudoDiceSeed>()];
+        bytes.iter_mut().enumerate().for_each(|(index, byte)| *byte = u8::from_str_radix(&seed[2 * index..2 * index + 2], 16).expect("checked to be hex digits"));
+        Ok(Some(bytes))
+    }
+
+    pub fn dice_configuration(&self) -> Result<LudoDiceConfiguration> {
+        Ok(LudoDiceConfiguration::new(self.dice_commit_reveal, self.dice_seed()?))
+    }
+
+    pub fn public_addresses(&self) -> Vec<SocketAddr> {
+        if self.public_addresses.is_empty() {
+            vec![self.address]
+        } else {
+            self.public_addresses.clone()
+        }
+    }
+
+    pub fn room_start_delay(&self) -> Duration {
+        Duration::from_millis(self.room_start_delay_ms)
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_millis(self.handshake_timeout_ms)
+    }
+
+    pub fn handshake_block(&self) -> Duration {
+        Duration::from_millis(self.handshake_block_ms)
+    }
+
+    pub fn backup_interval(&self) -> Duration {
+        Duration::from_millis(self.backup_interval_ms)
+    }
+
+    pub fn snapshot_interval(&self) -> Duration {
+        Duration::from_millis(self.snapshot_interval_ms)
+    }
+
+    pub fn profiles_broadcast_interval(&self) -> Duration {
+        Duration::from_millis(self.profiles_broadcast_interval_ms)
+    }
+
+    pub fn matchmaking_timeout(&self) -> Duration {
+        Duration::from_millis(self.matchmaking_timeout_ms)
+    }
+
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_millis(self.reconnect_grace_ms)
+    }
+
+    pub fn bot_delay(&self) -> Duration {
+        Duration::from_millis(self.bot_delay_ms)
+    }
+
+    pub fn turn_timeout(&self) -> Duration {
+        Duration::from_millis(self.turn_timeout_ms)
+    }
+
+    pub fn closing_duration(&self) -> Duration {
+        Duration::from_millis(self.closing_duration_ms)
+    }
+}
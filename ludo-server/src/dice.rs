@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use derive_new::new;
+use ludo_commons::dice::{LudoDice, LudoDiceRoll, LudoDiceSeed};
+use ludo_commons::packets::{LudoGameOutcomeDiceCommitmentPacket, LudoGameOutcomeDiceRevealPacket};
+use crate::room::LudoRoom;
+use crate::server::LudoOnlineClientPool;
+
+#[derive(Resource, new)]
+pub struct LudoDiceConfiguration {
+    /* publish the hash of the seed at match start and the seed itself at match end. */
+    pub commit_reveal: bool,
+    /* fixed seed for reproducible matches, a fresh one is drawn per match otherwise. */
+    pub seed: Option<LudoDiceSeed>,
+}
+
+/* the only place where dice are rolled, one per match on the entity of its room. */
+#[derive(Component)]
+pub struct LudoDiceService {
+    pub dice: LudoDice,
+    pub commit_reveal: bool,
+    /* rolls of the running match, sent to reconnecting clients. */
+    pub history: Vec<LudoDiceRoll>,
+}
+
+impl LudoDiceService {
+    pub fn new(configuration: &LudoDiceConfiguration) -> Self {
+        let dice = match configuration.seed {
+            Some(seed) => LudoDice::new(seed),
+            None => LudoDice::from_entropy(),
+        };
+        Self { dice, commit_reveal: configuration.commit_reveal, history: vec![] }
+    }
+
+    pub fn roll(&mut self) -> LudoDiceRoll {
+        let roll = self.dice.roll();
+        self.history.push(roll);
+        roll
+    }
+
+    pub fn publish_commitment(&self, room: &LudoRoom, pool: &LudoOnlineClientPool, server: &mut ResMut<RenetServer>) {
+        if self.commit_reveal {
+            let commitment = self.dice.commitment();
+            room.broadcast(LudoGameOutcomeDiceCommitmentPacket::new(commitment), pool, server);
+            info!("dice commitment of room {} published: {:02x?}", room.id, commitment);
+        }
+    }
+
+    pub fn reveal(&self, room: &LudoRoom, pool: &LudoOnlineClientPool, server: &mut ResMut<RenetServer>) {
+        if self.commit_reveal {
+            let seed = self.dice.seed();
+            room.broadcast(LudoGameOutcomeDiceRevealPacket::new(seed), pool, server);
+            info!("dice seed of room {} revealed after {} rolls: {:02x?}", room.id, self.dice.rolls(), seed);
+        }
+    }
+}
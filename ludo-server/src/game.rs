@@ -0,0 +1,489 @@
+use std::time::{Instant, SystemTime};
+use anyhow::{Error, Result};
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use ludo_commons::bot::LudoBotDifficulty;
+use ludo_commons::game::{LudoGamePlacement, LudoGameProfile, LudoGameState, LudoMatchSummary};
+use ludo_commons::rules::{LudoBoardState, LudoColor, LudoMove, LudoRuleSet, LudoTurnOptions};
+use ludo_commons::packets::{LudoGameIncomeMovePacket, LudoGameIncomeRollPacket, LudoGameOutcomeBoardPacket, LudoGameOutcomeDiceRollPacket, LudoGameOutcomeGameResultPacket, LudoGameOutcomeGameStartPacket, LudoGameOutcomeProfilePacket, LudoGameOutcomeSnapshotPacket, LudoGameOutcomeTurnPacket};
+use crate::configuration::LudoServerConfiguration;
+use crate::dice::{LudoDiceConfiguration, LudoDiceService};
+use crate::turn::LudoTurnTimer;
+use crate::handler::LudoIncomePacketEvent;
+use crate::room::{LudoRoom, LudoRoomIndex};
+use crate::server::LudoOnlineClientPool;
+use crate::store::{LudoMatchResult, LudoProfiles};
+
+/* a six grants another roll, but the third six in a row forfeits the turn. */
+pub const LUDO_MAX_SIXES_IN_A_ROW: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LudoSeatOccupant {
+    Client(ClientId),
+    Bot(LudoBotDifficulty),
+}
+
+#[derive(Debug, Clone)]
+pub struct LudoSeat {
+    pub occupant: LudoSeatOccupant,
+    pub color: LudoColor,
+    pub profile: Option<LudoGameProfile>,
+    /* turn timeouts in a row, reset by every action of the player itself. */
+    pub timeouts: u8,
+    /* the current turn already counted a timeout, the server may act for the player more than once per turn. */
+    pub timed_out: bool,
+    pub captures: u32,
+    pub tokens_lost: u32,
+    pub rolled_sixes: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum LudoTurnPhase {
+    AwaitingRoll,
+    AwaitingMove(u8, Vec<LudoMove>),
+}
+
+/* the server held board model and whose turn it is, a component of the room entity. */
+#[derive(Component, Debug, Clone)]
+pub struct LudoServerMatch {
+    pub board: LudoBoardState,
+    pub seats: Vec<LudoSeat>,
+    pub current: usize,
+    pub phase: LudoTurnPhase,
+    pub sixes: u8,
+    /* colours in the order they brought their last token home. */
+    pub placements: Vec<LudoColor>,
+    /* counts every roll, move and pass, the turn timer restarts whenever it changes. */
+    pub steps: u32,
+    pub started_at: Instant,
+}
+
+/* points added to the profile data per finishing place, first place first. */
+#[derive(Resource)]
+pub struct LudoPlacementPoints(pub Vec<u128>);
+
+#[derive(Component)]
+pub struct LudoClosingTimer(pub Timer);
+
+/* paces the bots of a room, one action per tick. */
+#[derive(Component)]
+pub struct LudoBotTimer(pub Timer);
+
+impl LudoSeat {
+    pub fn client_id(&self) -> Option<ClientId> {
+        match self.occupant {
+            LudoSeatOccupant::Client(client_id) => Some(client_id),
+            LudoSeatOccupant::Bot(_) => None,
+        }
+    }
+
+    /* counts a timeout at most once per turn, returns whether this one counted. */
+    pub fn record_timeout(&mut self) -> bool {
+        if self.timed_out {
+            return false;
+        }
+        self.timed_out = true;
+        self.timeouts += 1;
+        true
+    }
+
+    pub fn nickname(&self) -> Option<String> {
+        match self.occupant {
+            LudoSeatOccupant::Bot(difficulty) => Some(format!("{:?} bot", difficulty)),
+            LudoSeatOccupant::Client(_) => self.profile.as_ref().map(|profile| profile.nickname.clone()),
+        }
+    }
+}
+
+impl LudoServerMatch {
+    pub fn new(clients: Vec<(ClientId, Option<LudoGameProfile>)>, bots: Vec<LudoBotDifficulty>, rules: LudoRuleSet) -> Self {
+        let colors = LudoColor::seating(clients.len() + bots.len());
+        let occupants = clients.into_iter().map(|(client_id, profile)| (LudoSeatOccupant::Client(client_id), profile)).chain(bots.into_iter().map(|difficulty| (LudoSeatOccupant::Bot(difficulty), None)));
+        let seats = occupants.zip(colors.iter()).map(|((occupant, profile), color)| LudoSeat { occupant, color: *color, profile, timeouts: 0, timed_out: false, captures: 0, tokens_lost: 0, rolled_sixes: 0 }).collect::<Vec<LudoSeat>>();
+        Self {
+            board: LudoBoardState::with_rules(&colors, rules),
+            seats,
+            current: 0,
+            phase: LudoTurnPhase::AwaitingRoll,
+            sixes: 0,
+            placements: vec![],
+            steps: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn active(&self) -> &LudoSeat {
+        &self.seats[self.current]
+    }
+
+    pub fn active_mut(&mut self) -> &mut LudoSeat {
+        &mut self.seats[self.current]
+    }
+
+    pub fn is_active(&self, client_id: ClientId) -> bool {
+        self.active().client_id() == Some(client_id)
+    }
+
+    /* applies a fresh roll of the active player and decides whether a move has to be chosen. */
+    pub fn roll(&mut self, roll: u8) {
+        self.steps += 1;
+        if roll == 6 {
+            self.sixes += 1;
+            self.active_mut().rolled_sixes += 1;
+            if self.sixes >= LUDO_MAX_SIXES_IN_A_ROW {
+                info!("{:?} rolled {} sixes in a row and forfeits the turn.", self.active().color, self.sixes);
+                self.next_turn();
+                return;
+            }
+        }
+        match self.board.legal_moves(self.active().color, roll) {
+            LudoTurnOptions::Moves(moves) => self.phase = LudoTurnPhase::AwaitingMove(roll, moves),
+            LudoTurnOptions::Pass => {
+                info!("{:?} has no legal move for a roll of {}.", self.active().color, roll);
+                self.finish_turn(roll == 6);
+            }
+        }
+    }
+
+    /* validates the chosen token against the legal moves of the current roll and applies it. */
+    pub fn play(&mut self, token: usize) -> Result<LudoMove> {
+        let LudoTurnPhase::AwaitingMove(roll, moves) = &self.phase else {
+            return Err(Error::msg("no roll to move with"));
+        };
+        let roll = *roll;
+        let chosen = moves.iter().find(|ludo_move| ludo_move.token == token).cloned().ok_or(Error::msg(format!("token {} can not move with a roll of {}", token, roll)))?;
+        self.board = self.board.apply(&chosen);
+        self.steps += 1;
+        self.active_mut().captures += chosen.captures.len() as u32;
+        for (color, _) in &chosen.captures {
+            if let Some(seat) = self.seats.iter_mut().find(|seat| seat.color == *color) {
+                seat.tokens_lost += 1;
+            }
+        }
+        if self.board.player(chosen.player).is_some_and(|board| board.has_finished()) && !self.placements.contains(&chosen.player) {
+            info!("{:?} finished on place {}!", chosen.player, self.placements.len() + 1);
+            self.placements.push(chosen.player);
+        }
+        self.finish_turn(self.board.rules.grants_extra_roll(&chosen));
+        Ok(chosen)
+    }
+
+    /* skips the active player, used when the seat can not act anymore. */
+    pub fn pass(&mut self) {
+        self.steps += 1;
+        self.next_turn();
+    }
+
+    fn finish_turn(&mut self, extra_roll: bool) {
+        if extra_roll && !self.board.player(self.active().color).is_some_and(|board| board.has_finished()) {
+            self.phase = LudoTurnPhase::AwaitingRoll;
+        } else {
+            self.next_turn();
+        }
+    }
+
+    fn next_turn(&mut self) {
+        self.sixes = 0;
+        self.active_mut().timed_out = false;
+        self.phase = LudoTurnPhase::AwaitingRoll;
+        for _ in 0..self.seats.len() {
+            self.current = (self.current + 1) % self.seats.len();
+            if !self.board.player(self.active().color).is_some_and(|board| board.has_finished()) {
+                break;
+            }
+        }
+    }
+
+    /* the match ends as soon as a single player is left on the board. */
+    pub fn is_over(&self) -> bool {
+        let remaining = self.seats.len() - self.placements.len();
+        remaining == 0 || (self.seats.len() > 1 && remaining <= 1)
+    }
+
+    /* finished players by their finishing order, everybody else behind them by how far their tokens got. */
+    pub fn final_placements(&self) -> Vec<&LudoSeat> {
+        let mut placed = self.placements.iter().filter_map(|color| self.seats.iter().find(|seat| seat.color == *color)).collect::<Vec<&LudoSeat>>();
+        let mut remaining = self.seats.iter().filter(|seat| !self.placements.contains(&seat.color)).collect::<Vec<&LudoSeat>>();
+        remaining.sort_by_key(|seat| std::cmp::Reverse(self.progress(seat.color)));
+        placed.append(&mut remaining);
+        placed
+    }
+
+    fn progress(&self, color: LudoColor) -> u32 {
+        self.board.player(color).map(|board| board.tokens.iter().filter_map(|token| token.progress()).map(u32::from).sum()).unwrap_or(0)
+    }
+
+    pub fn turn_packet(&self) -> LudoGameOutcomeTurnPacket {
+        match &self.phase {
+            LudoTurnPhase::AwaitingRoll => LudoGameOutcomeTurnPacket::new(self.active().color, None, vec![]),
+            LudoTurnPhase::AwaitingMove(roll, moves) => LudoGameOutcomeTurnPacket::new(self.active().color, Some(*roll), moves.clone()),
+        }
+    }
+
+    /* bots and absent players only act while somebody of the room is connected. */
+    pub fn has_present_client(&self, room: &LudoRoom) -> bool {
+        self.seats.iter().filter_map(|seat| seat.client_id()).any(|client_id| room.members.contains(&client_id))
+    }
+
+    pub fn snapshot(&self, room: &LudoRoom, color: Option<LudoColor>, dice: &LudoDiceService) -> LudoGameOutcomeSnapshotPacket {
+        let commitment = dice.commit_reveal.then(|| dice.dice.commitment());
+        LudoGameOutcomeSnapshotPacket::new(room.info(), color, self.board.clone(), self.turn_packet(), commitment, dice.history.clone())
+    }
+
+    pub fn broadcast(&self, room: &LudoRoom, pool: &LudoOnlineClientPool, server: &mut ResMut<RenetServer>) {
+        room.broadcast(LudoGameOutcomeBoardPacket::new(self.board.clone()), pool, server);
+        room.broadcast(self.turn_packet(), pool, server);
+    }
+}
+
+/* seats every member of the room, fills the free seats with bots if the room wants them and puts the match and its dice next to the room. */
+pub fn start_match(commands: &mut Commands, entity: Entity, room: &mut LudoRoom, pool: &mut LudoOnlineClientPool, dice_configuration: &LudoDiceConfiguration, configuration: &LudoServerConfiguration, server: &mut ResMut<RenetServer>) {
+    let clients = room.members.iter().filter_map(|client| pool.session(client)).filter(|session| session.is_admitted()).map(|session| (session.client_id, session.profile.clone())).collect::<Vec<(ClientId, Option<LudoGameProfile>)>>();
+    let bots = room.bots.map(|difficulty| vec![difficulty; (room.max_players as usize).saturating_sub(clients.len())]).unwrap_or_default();
+    let ludo_match = LudoServerMatch::new(clients, bots, room.rules.clone());
+    ludo_match.seats.iter().for_each(|seat| match seat.occupant {
+        LudoSeatOccupant::Client(client_id) => {
+            if let Some(session) = pool.session_mut(&client_id) {
+                session.color = Some(seat.color);
+            }
+            pool.send_packet(client_id, LudoGameOutcomeGameStartPacket::new(seat.color), server);
+            info!("client {} plays {:?} in room {}.", client_id, seat.color, room.id);
+        }
+        LudoSeatOccupant::Bot(difficulty) => info!("{:?} bot plays {:?} in room {}.", difficulty, seat.color, room.id),
+    });
+    let dice = LudoDiceService::new(dice_configuration);
+    dice.publish_commitment(room, pool, server);
+    ludo_match.broadcast(room, pool, server);
+    room.state = LudoGameState::InGame;
+    commands.entity(entity).insert((ludo_match, dice, LudoBotTimer(Timer::new(configuration.bot_delay(), TimerMode::Repeating)), LudoTurnTimer::new(configuration.turn_timeout())));
+    info!("ludo match of room {} started!", room.id);
+}
+
+pub fn handle_turn_roll_system(
+    mut roll_events: EventReader<LudoIncomePacketEvent<LudoGameIncomeRollPacket>>,
+    index: Res<LudoRoomIndex>,
+    mut rooms: Query<(&LudoRoom, &mut LudoServerMatch, &mut LudoDiceService)>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, .. } in roll_events.read() {
+        let Some((room, mut ludo_match, mut dice)) = index.entity_of(&pool, client_id).and_then(|entity| rooms.get_mut(entity).ok()) else {
+            warn!("client {} tried to roll outside of a match.", client_id);
+            continue;
+        };
+        if !ludo_match.is_active(*client_id) || !matches!(ludo_match.phase, LudoTurnPhase::AwaitingRoll) {
+            warn!("client {} tried to roll out of turn.", client_id);
+            continue;
+        }
+        ludo_match.active_mut().timeouts = 0;
+        roll_active(room, &mut ludo_match, &mut dice, &pool, &mut server);
+    }
+}
+
+/* rolls the dice for the active seat, the caller made sure a roll is expected. */
+pub fn roll_active(room: &LudoRoom, ludo_match: &mut LudoServerMatch, dice: &mut LudoDiceService, pool: &LudoOnlineClientPool, server: &mut ResMut<RenetServer>) {
+    let color = ludo_match.active().color;
+    let roll = dice.roll();
+    info!("{:?} rolled a {} (roll #{}) in room {}.", color, roll.value, roll.sequence, room.id);
+    room.broadcast(LudoGameOutcomeDiceRollPacket::new(color, roll), pool, server);
+    ludo_match.roll(roll.value);
+    ludo_match.broadcast(room, pool, server);
+}
+
+pub fn handle_turn_move_system(
+    mut move_events: EventReader<LudoIncomePacketEvent<LudoGameIncomeMovePacket>>,
+    index: Res<LudoRoomIndex>,
+    mut rooms: Query<(&LudoRoom, &mut LudoServerMatch)>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, packet } in move_events.read() {
+        let Some((room, mut ludo_match)) = index.entity_of(&pool, client_id).and_then(|entity| rooms.get_mut(entity).ok()) else {
+            warn!("client {} tried to move outside of a match.", client_id);
+            continue;
+        };
+        if !ludo_match.is_active(*client_id) {
+            warn!("client {} tried to move out of turn.", client_id);
+            continue;
+        }
+        let current = ludo_match.current;
+        match ludo_match.play(packet.token) {
+            Ok(ludo_move) => {
+                ludo_match.seats[current].timeouts = 0;
+                info!("{:?} moved token {} to {:?}.", ludo_move.player, ludo_move.token, ludo_move.to);
+                ludo_match.broadcast(room, &pool, &mut server);
+            }
+            Err(error) => warn!("rejected move of client {}: {}", client_id, error),
+        }
+    }
+}
+
+/* a player who left the match can not roll anymore, so the turn moves on without them. */
+pub fn handle_turn_absent_system(mut rooms: Query<(&LudoRoom, &mut LudoServerMatch)>, pool: Res<LudoOnlineClientPool>, mut server: ResMut<RenetServer>) {
+    for (room, mut ludo_match) in rooms.iter_mut() {
+        let absent = ludo_match.active().client_id().is_some_and(|client_id| !room.members.contains(&client_id));
+        if room.state == LudoGameState::InGame && absent && ludo_match.has_present_client(room) {
+            info!("{:?} left the match, skipping the turn.", ludo_match.active().color);
+            ludo_match.pass();
+            ludo_match.broadcast(room, &pool, &mut server);
+        }
+    }
+}
+
+/* awards the placement points, announces the results and reveals the dice seed of every finished match. */
+pub fn handle_game_over_system(
+    mut commands: Commands,
+    mut rooms: Query<(Entity, &mut LudoRoom, &LudoServerMatch, &LudoDiceService)>,
+    points: Res<LudoPlacementPoints>,
+    configuration: Res<LudoServerConfiguration>,
+    mut profiles: ResMut<LudoProfiles>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for (entity, mut room, ludo_match, dice) in rooms.iter_mut() {
+        if room.state != LudoGameState::InGame || !ludo_match.is_over() {
+            continue;
+        }
+        let seats = ludo_match.final_placements();
+        let finished_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let game_secs = ludo_match.started_at.elapsed().as_secs();
+        let mut results = vec![];
+        let placements = seats.iter().enumerate().map(|(place, seat)| {
+            let awarded = points.0.get(place).copied().unwrap_or(0);
+            if let Some(profile) = &seat.profile {
+                let summary = LudoMatchSummary::new(finished_at, place as u8 + 1, seats.len() as u8, awarded, seat.captures, seat.tokens_lost, seat.rolled_sixes, game_secs);
+                results.push(LudoMatchResult::new(profile.unique_id, summary));
+            }
+            info!("place {}: {:?} (+{} points)", place + 1, seat.color, awarded);
+            LudoGamePlacement::new(place as u8 + 1, seat.color, seat.nickname(), awarded)
+        }).collect::<Vec<LudoGamePlacement>>();
+        match profiles.0.record_match(&results) {
+            Ok(updated) => updated.into_iter().for_each(|data| {
+                let seat = seats.iter().find(|seat| seat.profile.as_ref().is_some_and(|profile| profile.unique_id == data.unique_id));
+                if let Some(client_id) = seat.and_then(|seat| seat.client_id()).filter(|client_id| room.members.contains(client_id)) {
+                    pool.send_packet(client_id, LudoGameOutcomeProfilePacket::new(data), &mut server);
+                }
+            }),
+            Err(error) => error!("unable to record the match of room {}: {}", room.id, error),
+        }
+        room.broadcast(LudoGameOutcomeGameResultPacket::new(placements), &pool, &mut server);
+        dice.reveal(&room, &pool, &mut server);
+        room.state = LudoGameState::Closing;
+        commands.entity(entity).remove::<LudoDiceService>().insert(LudoClosingTimer(Timer::new(configuration.closing_duration(), TimerMode::Once)));
+        info!("ludo match of room {} is over!", room.id);
+    }
+}
+
+/* after the results were shown the room waits for its next match. */
+pub fn update_closing_timer(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rooms: Query<(Entity, &mut LudoRoom, &mut LudoClosingTimer)>,
+    mut pool: ResMut<LudoOnlineClientPool>
+) {
+    rooms.iter_mut().for_each(|(entity, mut room, mut timer)| {
+        if timer.0.tick(time.delta()).just_finished() {
+            commands.entity(entity).remove::<(LudoClosingTimer, LudoServerMatch, LudoBotTimer, LudoTurnTimer)>();
+            room.reservations.clear();
+            room.members.iter().for_each(|client| {
+                if let Some(session) = pool.session_mut(client) {
+                    session.color = None;
+                }
+            });
+            room.state = LudoGameState::Waiting;
+            info!("room {} is waiting for the next ludo match...", room.id);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ludo_commons::dice::{self, LudoDice, LudoDiceSeed};
+    use ludo_commons::rules::LudoTokenPosition;
+    use super::*;
+
+    /* the first seed whose dice start with the given rolls. */
+    fn seed_rolling(rolls: &[u8]) -> LudoDiceSeed {
+        (0u64..).map(|counter| {
+            let mut seed = [0u8; 32];
+            seed[..8].copy_from_slice(&counter.to_le_bytes());
+            seed
+        }).find(|seed| dice::replay(*seed, rolls.len() as u32).iter().map(|roll| roll.value).eq(rolls.iter().copied())).unwrap()
+    }
+
+    fn bot_match(rules: LudoRuleSet) -> LudoServerMatch {
+        LudoServerMatch::new(vec![], vec![LudoBotDifficulty::Random; 2], rules)
+    }
+
+    fn roll(ludo_match: &mut LudoServerMatch, dice: &mut LudoDice) {
+        ludo_match.roll(dice.roll().value);
+    }
+
+    #[test]
+    fn a_six_grants_another_roll() {
+        let mut dice = LudoDice::new(seed_rolling(&[6, 3]));
+        let mut ludo_match = bot_match(LudoRuleSet::default());
+        roll(&mut ludo_match, &mut dice);
+        ludo_match.play(0).unwrap();
+        assert_eq!((ludo_match.current, ludo_match.sixes), (0, 1));
+        assert!(matches!(ludo_match.phase, LudoTurnPhase::AwaitingRoll));
+        roll(&mut ludo_match, &mut dice);
+        ludo_match.play(0).unwrap();
+        assert_eq!((ludo_match.current, ludo_match.sixes), (1, 0));
+        assert_eq!(ludo_match.board.player(LudoColor::Red).unwrap().tokens[0], LudoTokenPosition::Track(3));
+    }
+
+    #[test]
+    fn the_third_six_in_a_row_forfeits_the_turn() {
+        let mut dice = LudoDice::new(seed_rolling(&[6, 6, 6]));
+        let mut ludo_match = bot_match(LudoRuleSet::default());
+        for _ in 1..LUDO_MAX_SIXES_IN_A_ROW {
+            roll(&mut ludo_match, &mut dice);
+            ludo_match.play(0).unwrap();
+            assert_eq!(ludo_match.current, 0);
+        }
+        roll(&mut ludo_match, &mut dice);
+        assert_eq!((ludo_match.current, ludo_match.sixes), (1, 0));
+        assert!(matches!(ludo_match.phase, LudoTurnPhase::AwaitingRoll));
+        assert_eq!(ludo_match.seats[0].rolled_sixes, LUDO_MAX_SIXES_IN_A_ROW as u32);
+        /* the forfeited six is never moved. */
+        assert_eq!(ludo_match.board.player(LudoColor::Red).unwrap().tokens[0], LudoTokenPosition::Track(6));
+    }
+
+    #[test]
+    fn a_roll_without_a_legal_move_ends_the_turn() {
+        let mut dice = LudoDice::new(seed_rolling(&[3]));
+        let mut ludo_match = bot_match(LudoRuleSet::default());
+        roll(&mut ludo_match, &mut dice);
+        assert_eq!((ludo_match.current, ludo_match.steps), (1, 1));
+        assert!(ludo_match.play(0).is_err());
+    }
+
+    #[test]
+    fn a_turn_counts_one_timeout() {
+        let mut ludo_match = bot_match(LudoRuleSet::default());
+        assert!(ludo_match.active_mut().record_timeout());
+        assert!(!ludo_match.active_mut().record_timeout());
+        assert_eq!(ludo_match.seats[0].timeouts, 1);
+        ludo_match.pass();
+        ludo_match.pass();
+        assert!(ludo_match.active_mut().record_timeout());
+        assert_eq!(ludo_match.seats[0].timeouts, 2);
+    }
+
+    #[test]
+    fn a_capture_grants_an_extra_roll_only_with_the_house_rule() {
+        for bonus_roll_on_capture in [false, true] {
+            let mut dice = LudoDice::new(seed_rolling(&[3]));
+            let mut ludo_match = bot_match(LudoRuleSet { bonus_roll_on_capture, ..LudoRuleSet::default() });
+            ludo_match.board.player_mut(LudoColor::Red).unwrap().tokens[0] = LudoTokenPosition::Track(1);
+            /* absolute square 4, three steps ahead of the red token. */
+            ludo_match.board.player_mut(LudoColor::Yellow).unwrap().tokens[0] = LudoTokenPosition::Track(30);
+            roll(&mut ludo_match, &mut dice);
+            let ludo_move = ludo_match.play(0).unwrap();
+            assert_eq!(ludo_move.captures, vec![(LudoColor::Yellow, 0)]);
+            assert_eq!((ludo_match.seats[0].captures, ludo_match.seats[1].tokens_lost), (1, 1));
+            assert_eq!(ludo_match.current, if bonus_roll_on_capture { 0 } else { 1 });
+        }
+    }
+}
@@ -1,80 +1,206 @@
 use bevy::log::warn;
+use crate::handshake::{LudoHandshakeAttempts, LudoHandshakeKey};
 use crate::server::LudoOnlineClientPool;
+use crate::session::LudoHandshakePhase;
+use std::time::Instant;
 use bevy::prelude::*;
-use bevy_renet::renet::{DefaultChannel, RenetServer};
-use ludo_commons::{security, LudoPacket, Pair};
-use ludo_commons::game::LudoGameProfileData;
-use ludo_commons::packets::{LudoGameIncomeHandshakePacket, LudoGameIncomeProfilePacket, LudoGameOutcomeProfilePacket};
+use bevy_renet::netcode::NetcodeServerTransport;
+use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer};
+use derive_new::new;
+use ludo_commons::{protocol, security, LudoClientMessage, LudoMessage, LudoPacket, LudoPacketDispatcher};
+use ludo_commons::game::{LudoGameProfileData, LudoGameStatistics};
+use crate::store::LudoProfiles;
+use ludo_commons::packets::{LudoGameIncomeHandshakePacket, LudoGameIncomeProfilePacket, LudoGameIncomeStatisticsPacket, LudoGameOutcomeDisconnectPacket, LudoGameOutcomeProfilePacket, LudoGameOutcomeStatisticsPacket};
 
+/* one typed event per incoming packet type, registered with `LudoIncomePacketApp::add_income_packet`. */
+#[derive(Event, new)]
+pub struct LudoIncomePacketEvent<T> where T: LudoPacket + Send + Sync + 'static {
+    pub client_id: ClientId,
+    pub packet: T,
+}
+
+pub trait LudoIncomePacketApp {
+    fn add_income_packet<T>(&mut self) -> &mut Self where T: LudoPacket + Send + Sync + 'static;
+}
+
+impl LudoIncomePacketApp for App {
+    fn add_income_packet<T>(&mut self) -> &mut Self where T: LudoPacket + Send + Sync + 'static {
+        self.add_event::<LudoIncomePacketEvent<T>>()
+    }
+}
+
+struct LudoIncomeDispatcher<'a, 'w, 's> {
+    commands: &'a mut Commands<'w, 's>,
+    client_id: ClientId,
+}
+
+impl LudoPacketDispatcher for LudoIncomeDispatcher<'_, '_, '_> {
+    fn dispatch<T>(&mut self, packet: T) where T: LudoPacket + Send + Sync + 'static {
+        self.commands.send_event(LudoIncomePacketEvent::new(self.client_id, packet));
+    }
+}
+
+/* decodes every incoming message once and hands it to the event of its packet type. */
 pub fn handle_client_income(
     mut commands: Commands,
     mut server: ResMut<RenetServer>,
-    mut ludo_client_pool: ResMut<LudoOnlineClientPool>,
-    mut profile_data: Query<&mut LudoGameProfileData>
+    mut ludo_client_pool: ResMut<LudoOnlineClientPool>
 ) {
-    let mut clients_to_remove = Vec::new();
     let client_ids: Vec<_> = server.clients_id_iter().collect();
     for client_id in client_ids {
+        let latency = server.network_info(client_id).ok().map(|network_info| network_info.rtt);
+        if let Some(session) = ludo_client_pool.session_mut(&client_id) {
+            session.latency = latency;
+        }
         while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered) {
-            match ludo_client_pool.ludo_clients_pool.get_mut(&client_id) {
-                None => {
-                    server.disconnect(client_id);
-                    warn!("Ludo client not registered: {}", client_id);
-                    info!("Connection closed: {}", client_id);
-                }
-                Some(client_data) => {
-                    let raw_data = String::from_utf8_lossy(&message).to_string();
-                    if let Ok(handshake_packet) = <LudoGameIncomeHandshakePacket as LudoPacket>::make_packet::<LudoGameIncomeHandshakePacket>(raw_data.clone()) {
-                    for items in client_data.iter_mut() {
-                        if items.0.eq("client.handshake") {
-                            if let Some(value) = items.1.downcast_ref::<bool>() {
-                                if !*value {
-                                    if handshake_packet.key.eq(&security::SECRET_KEY) {
-                                        info!("client successfully handshake: {}", client_id);
-                                        items.1 = Box::new(true);
-                                    } else {
-                                        server.disconnect(client_id);
-                                        clients_to_remove.push(client_id);
-                                        warn!("wrong security key from: {}", client_id);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    if let Ok(profile_income_packet) = <LudoGameIncomeProfilePacket as LudoPacket>::make_packet::<LudoGameIncomeProfilePacket>(raw_data.clone()) {
-                        ludo_client_pool.ludo_clients_pool.get_mut(&client_id).unwrap().push(Pair::new("server.profile".to_string(), Box::new(profile_income_packet.profile.clone())));
-                        let mut found = false;
-                        profile_data.iter_mut().for_each(|profile_data| {
-                            if profile_data.unique_id.eq(&profile_income_packet.profile.unique_id.clone()) {
-                                let packet = LudoGameOutcomeProfilePacket::new(profile_data.clone());
-                                server.send_message(client_id, DefaultChannel::ReliableOrdered, packet.into_string::<LudoGameOutcomeProfilePacket>().unwrap());
-                                found = true;
-                                info!("Client successfully sent profile data: {:?}", profile_income_packet.profile);
-                            }
-                        });
-                        if !found {
-                            info!("Client profile not found in database: {:?}", profile_income_packet.profile.unique_id);
-                            let profile_data = LudoGameProfileData {
-                                unique_id: profile_income_packet.profile.unique_id.clone(),
-                                points: 0,
-                            };
-                            commands.spawn(profile_data.clone());
-                            let packet = LudoGameOutcomeProfilePacket::new(profile_data);
-                            server.send_message(client_id, DefaultChannel::ReliableOrdered, packet.into_string::<LudoGameOutcomeProfilePacket>().unwrap());
-                            info!("Client successfully sent profile data: {:?}", profile_income_packet.profile);
-                            info!("a new profile data were created!")
-                        }
-                    }
-                }
+            let Some(session) = ludo_client_pool.session_mut(&client_id) else {
+                server.disconnect(client_id);
+                warn!("Ludo client not registered: {}", client_id);
+                info!("Connection closed: {}", client_id);
+                break;
+            };
+            session.last_activity = Instant::now();
+            match LudoClientMessage::decode(&message) {
+                Ok(income_message) => income_message.dispatch(&mut LudoIncomeDispatcher { commands: &mut commands, client_id }),
+                Err(error) => warn!("invalid packet from client {}: {}", client_id, error),
             }
         }
     }
 }
-if ! clients_to_remove.is_empty() {
+
+pub fn handle_handshake_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeHandshakePacket>>,
+    mut server: ResMut<RenetServer>,
+    server_transport: Res<NetcodeServerTransport>,
+    handshake_key: Res<LudoHandshakeKey>,
+    mut handshake_attempts: ResMut<LudoHandshakeAttempts>,
+    mut ludo_client_pool: ResMut<LudoOnlineClientPool>
+) {
+    let mut clients_to_remove = Vec::new();
+    for LudoIncomePacketEvent { client_id, packet: handshake_packet } in events.read() {
+        let client_id = *client_id;
+        if !protocol::is_compatible(handshake_packet.protocol_version) {
+            let reason = format!("incompatible protocol version {} (client build {}), the server speaks version {}!", handshake_packet.protocol_version, handshake_packet.client_build, protocol::LUDO_PROTOCOL_VERSION);
+            ludo_client_pool.send_packet(client_id, LudoGameOutcomeDisconnectPacket::new(reason), &mut server);
+            server.disconnect(client_id);
+            clients_to_remove.push(client_id);
+            warn!("incompatible protocol version {} from: {}", handshake_packet.protocol_version, client_id);
+            continue;
+        }
+        let address = server_transport.client_addr(client_id).map(|address| address.ip());
+        let Some(session) = ludo_client_pool.session(&client_id) else { continue };
+        if session.phase != LudoHandshakePhase::Challenged {
+            continue;
+        }
+        let verified = security::verify_handshake_response(&handshake_key.0, &session.nonce, &handshake_packet.response);
+        if !verified {
+            server.disconnect(client_id);
+            clients_to_remove.push(client_id);
+            match address {
+                Some(address) => warn!("wrong handshake response from: {} ({}), failed attempts: {}", client_id, address, handshake_attempts.record_failure(address, Instant::now())),
+                None => warn!("wrong handshake response from: {}", client_id),
+            }
+            continue;
+        }
+        if let Some(address) = address {
+            handshake_attempts.record_success(&address);
+        }
+        if let Some(session) = ludo_client_pool.session_mut(&client_id) {
+            session.phase = LudoHandshakePhase::Verified;
+            info!("client successfully handshake: {}", client_id);
+        }
+    }
+    if !clients_to_remove.is_empty() {
         for client_id in clients_to_remove {
-            ludo_client_pool.ludo_clients_pool.remove(&client_id);
+            ludo_client_pool.sessions.remove(&client_id);
+        }
+        info!("current connected clients: {0} and registered: {1}", server.connected_clients(), ludo_client_pool.sessions.len());
+    }
+}
+
+pub fn handle_profile_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeProfilePacket>>,
+    mut server: ResMut<RenetServer>,
+    server_transport: Res<NetcodeServerTransport>,
+    mut ludo_client_pool: ResMut<LudoOnlineClientPool>,
+    mut profiles: ResMut<LudoProfiles>
+) {
+    for LudoIncomePacketEvent { client_id, packet: profile_income_packet } in events.read() {
+        let client_id = *client_id;
+        let profile = &profile_income_packet.profile;
+        let token_profile = server_transport.user_data(client_id).map(|user_data| security::profile_id_of_user_data(&user_data));
+        let signed = ludo_client_pool.session(&client_id).is_some_and(|session| security::verify_profile(&profile.public_key, &session.nonce, &profile.unique_id, &profile_income_packet.signature));
+        let stored = match profiles.0.get(&profile.unique_id) {
+            Ok(stored) => stored,
+            Err(error) => {
+                error!("unable to read the profile of client {}: {}", client_id, error);
+                server.disconnect(client_id);
+                ludo_client_pool.sessions.remove(&client_id);
+                continue;
+            }
+        };
+        let registered_key = stored.as_ref().and_then(|profile_data| profile_data.public_key);
+        let rejection = if token_profile != Some(profile.unique_id) {
+            Some("the profile does not match the connect token!")
+        } else if !signed {
+            Some("the profile signature is invalid!")
+        } else if registered_key.is_some_and(|registered_key| registered_key != profile.public_key) {
+            Some("the profile is registered with another key!")
+        } else {
+            None
+        };
+        if let Some(reason) = rejection {
+            ludo_client_pool.send_packet(client_id, LudoGameOutcomeDisconnectPacket::new(reason.to_string()), &mut server);
+            server.disconnect(client_id);
+            ludo_client_pool.sessions.remove(&client_id);
+            warn!("profile of {} rejected: {}", client_id, reason);
+            continue;
         }
-        info!("current connected clients: {0} and registered: {1}", server.connected_clients(), ludo_client_pool.ludo_clients_pool.len());
+        let Some(session) = ludo_client_pool.session_mut(&client_id) else { continue };
+        session.profile = Some(profile_income_packet.profile.clone());
+        let profile_data = match stored {
+            Some(mut profile_data) => {
+                if profile_data.public_key.is_none() {
+                    profile_data.public_key = Some(profile_income_packet.profile.public_key);
+                    info!("profile claimed with its key: {:?}", profile_data.unique_id);
+                }
+                profile_data
+            }
+            None => {
+                info!("Client profile not found in database: {:?}", profile_income_packet.profile.unique_id);
+                LudoGameProfileData {
+                    unique_id: profile_income_packet.profile.unique_id.clone(),
+                    points: 0,
+                    public_key: Some(profile_income_packet.profile.public_key),
+                    statistics: LudoGameStatistics::default(),
+                }
+            }
+        };
+        if let Err(error) = profiles.0.upsert(&profile_data) {
+            error!("unable to store the profile of client {}: {}", client_id, error);
+        }
+        ludo_client_pool.send_packet(client_id, LudoGameOutcomeProfilePacket::new(profile_data), &mut server);
+        info!("Client successfully sent profile data: {:?}", profile_income_packet.profile);
+    }
+}
+
+/* statistics of any known profile, only for clients which sent their own profile. */
+pub fn handle_statistics_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeStatisticsPacket>>,
+    mut server: ResMut<RenetServer>,
+    ludo_client_pool: Res<LudoOnlineClientPool>,
+    profiles: Res<LudoProfiles>
+) {
+    for LudoIncomePacketEvent { client_id, packet: statistics_packet } in events.read() {
+        let Some(own) = ludo_client_pool.session(client_id).and_then(|session| session.profile.as_ref()).map(|profile| profile.unique_id) else { continue };
+        let unique_id = statistics_packet.unique_id.unwrap_or(own);
+        let statistics = match profiles.0.get(&unique_id) {
+            Ok(profile_data) => profile_data.map(|profile_data| profile_data.statistics),
+            Err(error) => {
+                error!("unable to read the statistics of {:?}: {}", unique_id, error);
+                None
+            }
+        };
+        ludo_client_pool.send_packet(*client_id, LudoGameOutcomeStatisticsPacket::new(unique_id, statistics), &mut server);
     }
-}
\ No newline at end of file
+}
@@ -1,121 +1,136 @@
-use ludo_commons::game::LudoGameConfiguration;
-use std::any::Any;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 use bevy::prelude::*;
-use bevy::utils::info;
-use bevy_renet::netcode::NetcodeServerTransport;
-use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer};
-use ludo_commons::{LudoPacket, Pair};
-use ludo_commons::game::{LudoGameObject, LudoGameState};
-use ludo_commons::packets::{LudoGameOutcomeDisconnectPacket, LudoGameOutcomeHandshakeCallbackPacket, LudoGameOutcomeServerDataPacket};
+use bevy::utils::{info, HashMap};
+use bevy_renet::renet::{ClientId, RenetServer};
+use ludo_commons::security::LudoKey;
+use ludo_commons::packets::LudoGameOutcomeHandshakeCallbackPacket;
 use crate::server::LudoOnlineClientPool;
+use crate::session::LudoHandshakePhase;
+
+/* failed handshakes after which an address is refused until its cooldown passed. */
+pub const LUDO_MAX_HANDSHAKE_FAILURES: u32 = 5;
 
 #[derive(Component)]
 pub struct HandshakeTimer(pub Timer, pub ClientId);
 
+/* secret shared with the clients, only its HMAC over a nonce is sent. */
+#[derive(Resource)]
+pub struct LudoHandshakeKey(pub LudoKey);
+
+/* failed handshakes per address together with the time of the last one, an address is forgiven after the cooldown. */
+#[derive(Resource)]
+pub struct LudoHandshakeAttempts {
+    pub failures: HashMap<IpAddr, (u32, Instant)>,
+    pub cooldown: Duration,
+}
+
+#[derive(Component)]
+pub struct LudoHandshakeAttemptsTimer(pub Timer);
+
+impl LudoHandshakeAttempts {
+    pub fn new(cooldown: Duration) -> Self {
+        Self { failures: HashMap::default(), cooldown }
+    }
+
+    pub fn is_blocked(&self, address: &IpAddr, now: Instant) -> bool {
+        self.failures.get(address).is_some_and(|(failures, last_failure)| *failures >= LUDO_MAX_HANDSHAKE_FAILURES && now.saturating_duration_since(*last_failure) < self.cooldown)
+    }
+
+    pub fn record_failure(&mut self, address: IpAddr, now: Instant) -> u32 {
+        let cooldown = self.cooldown;
+        let (failures, last_failure) = self.failures.entry(address).or_insert((0, now));
+        if now.saturating_duration_since(*last_failure) >= cooldown {
+            *failures = 0;
+        }
+        *failures += 1;
+        *last_failure = now;
+        *failures
+    }
+
+    /* forgets every address whose last failure is older than the cooldown. */
+    pub fn prune(&mut self, now: Instant) -> usize {
+        let before = self.failures.len();
+        let cooldown = self.cooldown;
+        self.failures.retain(|_, (_, last_failure)| now.saturating_duration_since(*last_failure) < cooldown);
+        before - self.failures.len()
+    }
+
+    pub fn record_success(&mut self, address: &IpAddr) {
+        self.failures.remove(address);
+    }
+}
+
+pub fn prune_handshake_attempts(time: Res<Time>, mut timer: Query<&mut LudoHandshakeAttemptsTimer>, mut handshake_attempts: ResMut<LudoHandshakeAttempts>) {
+    let Ok(mut timer) = timer.get_single_mut() else { return };
+    if timer.0.tick(time.delta()).just_finished() {
+        match handshake_attempts.prune(Instant::now()) {
+            0 => {}
+            pruned => info!("forgot the failed handshakes of {} addresses.", pruned),
+        }
+    }
+}
+
 pub fn update_handshake_timer(
+    mut commands: Commands,
     time: Res<Time>,
-    mut timer: Query<&mut HandshakeTimer>,
+    mut timer: Query<(Entity, &mut HandshakeTimer)>,
     mut online_clients: ResMut<LudoOnlineClientPool>,
-    mut server: ResMut<RenetServer>,
-    server_transport: Res<NetcodeServerTransport>,
-    game_object: Res<LudoGameObject>,
-    configuration: Res<LudoGameConfiguration>
+    mut server: ResMut<RenetServer>
 ) {
-    let mut clients_to_remove = Vec::new();
-    let mut successfully_removed: Option<Pair<i32, ClientId>> = None;
-    timer.iter_mut().for_each(|mut timer| {
-        if timer.0.tick(time.delta()).just_finished() {
-            let client_id = timer.1.clone();
-            let client_handshake = online_clients.ludo_clients_pool.get(&client_id);
-            if let Some(client_handshake) = client_handshake {
-                let mut i: i32 = -1;
-                for items in client_handshake {
-                    if items.0.eq("client.handshake") {
-                        if let Some(value) = items.1.downcast_ref::<bool>() {
-                            if *value {
-
-                                /* client can join */
-                                if game_object.state == LudoGameState::Waiting {
-                                    if online_clients.ludo_clients_pool.len() < 4 {
-                                        successfully_removed = Some(Pair::new(i+1, client_id));
-                                        server.send_message(
-                                            client_id.clone(),
-                                            DefaultChannel::ReliableOrdered,
-                                            LudoGameOutcomeHandshakeCallbackPacket::new().into_string::<LudoGameOutcomeHandshakeCallbackPacket>().expect("unable to serialize #(LudoGameOutcomeHandshakeCallbackPacket) packet!")
-                                        );
-
-                                        if let Some(address) = server_transport.client_addr(client_id) {
-                                            info!("Server handshake successfully! {}", address);
-                                        }
-                                    } else {
-                                        let packet = LudoGameOutcomeDisconnectPacket::new("the game is already full!".to_string());
-                                        server.send_message(
-                                            client_id,
-                                            DefaultChannel::ReliableOrdered,
-                                            packet.into_string::<LudoGameOutcomeDisconnectPacket>().expect("unable to serialize #(LudoGameOutcomeDisconnectPacket) packet!")
-                                        );
-                                        server.disconnect(client_id);
-                                    }
-                                } else {
-                                    let packet = LudoGameOutcomeDisconnectPacket::new("the game is already running!".to_string());
-                                    server.send_message(
-                                        client_id,
-                                        DefaultChannel::ReliableOrdered,
-                                        packet.into_string::<LudoGameOutcomeDisconnectPacket>().expect("unable to serialize #(LudoGameOutcomeDisconnectPacket) packet!")
-                                    );
-                                    server.disconnect(client_id);
-                                }
-
-                            } else {
-                                server.disconnect(client_id.clone());
-                                clients_to_remove.push(client_id);
-                                info!("no answer from client. id={}", client_id);
-                                info("client disconnected because of no successfully handshake.");
-                            }
-                        } else {
-                            server.disconnect(client_id.clone());
-                            clients_to_remove.push(client_id);
-                            info!("no answer from client. id={}", client_id);
-                            info("client disconnected.");
-                            error!("Expected boolean for this handshake query!");
-                            panic!("unexpected behavior!");
-                        }
-                    } else {
-                        let result = online_clients.ludo_clients_pool.get(&client_id).unwrap().iter().filter(|item| -> bool {
-                            item.0.eq("client.handshaked") == true
-                        }).collect::<Vec<&Pair<String, Box<dyn Any + Sync + Send>>>>();
-                        if result.is_empty() {
-                            let result = online_clients.ludo_clients_pool.get(&client_id).unwrap().iter().filter(|item| -> bool {
-                                item.0.eq("client.handshake") == true
-                            }).collect::<Vec<&Pair<String, Box<dyn Any + Sync + Send>>>>();
-                            if result.is_empty() {
-                                server.disconnect(client_id.clone());
-                                clients_to_remove.push(client_id);
-                                info!("no answer from client. id={}", client_id);
-                                info("client disconnected because of no entry.");
-                            }
-                        }
-                    }
-                    i+=1;
+    timer.iter_mut().for_each(|(entity, mut timer)| {
+        if !timer.0.tick(time.delta()).just_finished() {
+            return;
+        }
+        commands.entity(entity).despawn();
+        let client_id = timer.1;
+        let Some(session) = online_clients.session(&client_id) else { return };
+        match session.phase {
+            LudoHandshakePhase::Challenged => {
+                server.disconnect(client_id);
+                online_clients.sessions.remove(&client_id);
+                info!("no answer from client. id={}", client_id);
+                info("client disconnected because of no successfully handshake.");
+                info!("current connected clients: {0} and registered: {1}", server.connected_clients(), online_clients.sessions.len());
+            }
+            LudoHandshakePhase::Verified => {
+                let address = session.address;
+                /* client can join, the server data follows with the room it joins. */
+                online_clients.session_mut(&client_id).unwrap().phase = LudoHandshakePhase::Admitted;
+                online_clients.send_packet(client_id, LudoGameOutcomeHandshakeCallbackPacket::new(), &mut server);
+                if let Some(address) = address {
+                    info!("Server handshake successfully! {}", address);
                 }
+                info!("client is marked as handshaked: {}", client_id);
             }
+            LudoHandshakePhase::Admitted => {}
         }
     });
-    if let Some(pair) = successfully_removed {
-        if pair.0 != -1 {
-            let client_cached = online_clients.ludo_clients_pool.get_mut(&pair.1).unwrap();
-            client_cached.remove(pair.0.try_into().unwrap());
-            client_cached.push(Pair::new("client.handshaked".to_string(), Box::new(true)));
-            info!("client is marked as handshaked: {}", pair.1);
-            info!("current connected clients: {0} and registered: {1}", server.connected_clients(), client_cached.len());
-            online_clients.send_packet(pair.1, LudoGameOutcomeServerDataPacket::new(configuration.min_players_to_start.into()), &mut server);
-        }
-    } else {
-        if !clients_to_remove.is_empty() {
-            for client_id in clients_to_remove {
-                online_clients.ludo_clients_pool.remove(&client_id);
-            }
-            info!("current connected clients: {0} and registered: {1}", server.connected_clients(), online_clients.ludo_clients_pool.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+
+    #[test]
+    fn blocked_addresses_are_forgiven_after_the_cooldown() {
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let cooldown = Duration::from_secs(60);
+        let mut attempts = LudoHandshakeAttempts::new(cooldown);
+        let start = Instant::now();
+        for failure in 1..=LUDO_MAX_HANDSHAKE_FAILURES {
+            assert!(!attempts.is_blocked(&address, start));
+            assert_eq!(attempts.record_failure(address, start), failure);
         }
+        assert!(attempts.is_blocked(&address, start));
+        assert!(attempts.is_blocked(&address, start + cooldown - Duration::from_millis(1)));
+        assert_eq!(attempts.prune(start + cooldown - Duration::from_millis(1)), 0);
+        let forgiven = start + cooldown;
+        assert!(!attempts.is_blocked(&address, forgiven));
+        assert_eq!(attempts.record_failure(address, forgiven), 1);
+        assert_eq!(attempts.prune(forgiven + cooldown - Duration::from_millis(1)), 0);
+        assert_eq!(attempts.prune(forgiven + cooldown), 1);
+        assert!(attempts.failures.is_empty());
     }
 }
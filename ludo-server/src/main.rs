@@ -3,17 +3,46 @@ mod handshake;
 mod handler;
 mod backup;
 mod communication;
+mod dice;
+mod game;
+mod session;
+mod configuration;
+mod room;
+mod matchmaking;
+mod reconnect;
+mod bot;
+mod turn;
+mod store;
 
 use bevy::log::tracing_subscriber;
 use bevy::prelude::*;
 use bevy::state::app::StatesPlugin;
 use bevy_renet::netcode::NetcodeServerPlugin;
 use bevy_renet::RenetServerPlugin;
+use clap::Parser;
+use crate::configuration::{LudoServerArguments, LudoServerCommand, LudoServerConfiguration};
 use crate::server::LudoServerPlugin;
 
 fn main() {
     tracing_subscriber::fmt().compact().with_ansi(true).init();
+    let arguments = LudoServerArguments::parse();
+    let configuration = match LudoServerConfiguration::load(&arguments) {
+        Ok(configuration) => configuration,
+        Err(error) => {
+            error!("invalid server configuration {}: {}", arguments.config, error);
+            std::process::exit(1);
+        }
+    };
+    info!("loaded server configuration: {:?}", configuration);
+    if let Some(LudoServerCommand::Restore { snapshot }) = &arguments.command {
+        if let Err(error) = backup::restore(&configuration, snapshot) {
+            error!("unable to restore snapshot {}: {}", snapshot, error);
+            std::process::exit(1);
+        }
+        return;
+    }
     App::new()
+        .insert_resource(configuration)
         .add_plugins(MinimalPlugins)
         .add_plugins(StatesPlugin)
         .add_plugins(RenetServerPlugin)
@@ -0,0 +1,146 @@
+use std::time::Instant;
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use ludo_commons::game::LudoGameConfiguration;
+use ludo_commons::rules::LudoRuleSet;
+use ludo_commons::packets::{LudoGameIncomeCancelMatchPacket, LudoGameIncomeFindMatchPacket, LudoGameOutcomeMatchFoundPacket, LudoGameOutcomeQueuedPacket, LudoGameOutcomeRoomRejectedPacket};
+use crate::configuration::LudoServerConfiguration;
+use crate::handler::LudoIncomePacketEvent;
+use crate::room::{self, LudoRoomIndex};
+use crate::server::LudoOnlineClientPool;
+use crate::store::LudoProfiles;
+
+#[derive(Debug, Clone)]
+pub struct LudoQueueEntry {
+    pub client_id: ClientId,
+    /* player count the client asked for, always within the limits of the server. */
+    pub players: u8,
+    pub rules: LudoRuleSet,
+    pub points: u128,
+    pub queued_at: Instant,
+}
+
+/* quick play entries, the oldest entry first. */
+#[derive(Resource, Default)]
+pub struct LudoMatchmakingQueue {
+    pub entries: Vec<LudoQueueEntry>,
+}
+
+impl LudoQueueEntry {
+    /* a match of the asked size is always fine, after the timeout a smaller one as well. */
+    pub fn accepts(&self, size: u8, min_players: u8, configuration: &LudoServerConfiguration) -> bool {
+        size == self.players || (self.queued_at.elapsed() >= configuration.matchmaking_timeout() && size >= min_players && size < self.players)
+    }
+}
+
+impl LudoMatchmakingQueue {
+    pub fn contains(&self, client: &ClientId) -> bool {
+        self.entries.iter().any(|entry| entry.client_id == *client)
+    }
+
+    /* the entries to seat together with the anchor, closest in points first when balancing. */
+    fn group(&self, anchor: usize, size: u8, min_players: u8, configuration: &LudoServerConfiguration) -> Option<Vec<usize>> {
+        let entry = &self.entries[anchor];
+        if !entry.accepts(size, min_players, configuration) {
+            return None;
+        }
+        let mut candidates = self.entries.iter().enumerate().filter(|(index, other)| *index != anchor && other.rules == entry.rules && other.accepts(size, min_players, configuration)).map(|(index, _)| index).collect::<Vec<usize>>();
+        if candidates.len() + 1 < size as usize {
+            return None;
+        }
+        if configuration.matchmaking_balance {
+            candidates.sort_by_key(|index| self.entries[*index].points.abs_diff(entry.points));
+        }
+        candidates.truncate(size as usize - 1);
+        candidates.insert(0, anchor);
+        Some(candidates)
+    }
+
+    /* seats of the room for a group, the largest match every member accepts. Seats nobody queued for are left to bots. */
+    pub fn seats(&self, group: &[usize], min_players: u8, configuration: &LudoServerConfiguration) -> u8 {
+        let size = group.len() as u8;
+        let largest = group.iter().map(|index| self.entries[*index].players).max().unwrap_or(size);
+        (size..=largest).rev().find(|seats| group.iter().all(|index| self.entries[*index].accepts(*seats, min_players, configuration))).unwrap_or(size)
+    }
+
+    /* the next group to place, the oldest entry decides and the largest possible match wins. */
+    pub fn next_group(&self, min_players: u8, configuration: &LudoServerConfiguration) -> Option<Vec<usize>> {
+        (0..self.entries.len()).find_map(|anchor| {
+            (min_players..=self.entries[anchor].players).rev().find_map(|size| self.group(anchor, size, min_players, configuration))
+        })
+    }
+}
+
+pub fn handle_find_match_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeFindMatchPacket>>,
+    mut queue: ResMut<LudoMatchmakingQueue>,
+    configuration: Res<LudoGameConfiguration>,
+    profiles: Res<LudoProfiles>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, packet } in events.read() {
+        let players = packet.players.unwrap_or(configuration.max_players_to_start);
+        let rules = packet.rules.clone().unwrap_or(configuration.rules.clone());
+        let checked = room::check_lobby_client(&pool, client_id).and_then(|_| {
+            if queue.contains(client_id) {
+                Err("you are already searching a match!".to_string())
+            } else if players < configuration.min_players_to_start || players > configuration.max_players_to_start {
+                Err(format!("quick play matches have {}..={} players!", configuration.min_players_to_start, configuration.max_players_to_start))
+            } else {
+                rules.validate().map_err(|error| error.to_string())
+            }
+        });
+        if let Err(reason) = checked {
+            pool.send_packet(*client_id, LudoGameOutcomeRoomRejectedPacket::new(reason), &mut server);
+            continue;
+        }
+        let unique_id = pool.session(client_id).and_then(|session| session.profile.as_ref()).map(|profile| profile.unique_id);
+        let points = unique_id.and_then(|unique_id| profiles.0.get(&unique_id).unwrap_or_else(|error| {
+            error!("unable to read the profile of client {}: {}", client_id, error);
+            None
+        })).map(|data| data.points).unwrap_or(0);
+        queue.entries.push(LudoQueueEntry { client_id: *client_id, players, rules, points, queued_at: Instant::now() });
+        pool.send_packet(*client_id, LudoGameOutcomeQueuedPacket::new(players, queue.entries.len() as u32), &mut server);
+        info!("client {} is searching a match of {} players ({} points).", client_id, players, points);
+    }
+}
+
+pub fn handle_cancel_match_packet(mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeCancelMatchPacket>>, mut queue: ResMut<LudoMatchmakingQueue>) {
+    for LudoIncomePacketEvent { client_id, .. } in events.read() {
+        queue.entries.retain(|entry| entry.client_id != *client_id);
+        info!("client {} stopped searching a match.", client_id);
+    }
+}
+
+/* drops entries of clients which left or joined a room on their own and opens a room for every complete group. */
+pub fn update_matchmaking_system(
+    mut commands: Commands,
+    mut queue: ResMut<LudoMatchmakingQueue>,
+    mut index: ResMut<LudoRoomIndex>,
+    configuration: Res<LudoGameConfiguration>,
+    server_configuration: Res<LudoServerConfiguration>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    queue.entries.retain(|entry| pool.session(&entry.client_id).is_some_and(|session| session.room.is_none()));
+    while index.rooms.len() < server_configuration.max_rooms {
+        let Some(group) = queue.next_group(configuration.min_players_to_start, &server_configuration) else { break };
+        let rules = queue.entries[group[0]].rules.clone();
+        let seats = queue.seats(&group, configuration.min_players_to_start, &server_configuration);
+        let members = group.iter().map(|index| queue.entries[*index].client_id).collect::<Vec<ClientId>>();
+        queue.entries.retain(|entry| !members.contains(&entry.client_id));
+        let size = members.len() as u8;
+        /* a group smaller than asked for only forms after the timeout, bots take the missing seats. */
+        let (max_players, bots) = if server_configuration.bot_fill_quick_play && seats > size {
+            (seats, Some(server_configuration.bot_difficulty))
+        } else {
+            (size, None)
+        };
+        /* the queue already waited, the match starts right away. */
+        let room = index.open(&mut commands, "quick play".to_string(), size, max_players, rules, bots, Some(Instant::now()), &members, &mut pool, &mut server);
+        let players = members.iter().filter_map(|client| pool.session(client)).map(|session| session.profile.as_ref().map(|profile| profile.nickname.clone()).unwrap_or("unknown".to_string())).collect::<Vec<String>>();
+        members.iter().for_each(|client| pool.send_packet(*client, LudoGameOutcomeMatchFoundPacket::new(room.clone(), players.clone()), &mut server));
+        info!("matchmaking placed {} clients into room {}.", size, room.id);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Instant;
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use ludo_commons::game::LudoGameState;
+use ludo_commons::rules::LudoColor;
+use ludo_commons::packets::LudoGameIncomeProfilePacket;
+use crate::configuration::LudoServerConfiguration;
+use crate::dice::LudoDiceService;
+use crate::game::{LudoSeatOccupant, LudoServerMatch};
+use crate::handler::LudoIncomePacketEvent;
+use crate::room::{self, LudoRoom, LudoSeatReservation};
+use crate::server::LudoOnlineClientPool;
+
+/* reserves the seats of members which disconnected from a running match, an expired reservation is handed to a bot if configured. */
+pub fn reserve_dropped_seats_system(mut rooms: Query<(&mut LudoRoom, Option<&mut LudoServerMatch>)>, pool: Res<LudoOnlineClientPool>, configuration: Res<LudoServerConfiguration>) {
+    for (mut room, mut ludo_match) in rooms.iter_mut() {
+        let id = room.id;
+        let expired = room.reservations.iter().filter(|reservation| reservation.expires_at <= Instant::now()).map(|reservation| reservation.color).collect::<Vec<LudoColor>>();
+        room.reservations.retain(|reservation| !expired.contains(&reservation.color));
+        expired.iter().for_each(|color| {
+            info!("reserved seat {:?} of room {} expired.", color, id);
+            let seat = ludo_match.as_mut().and_then(|ludo_match| ludo_match.seats.iter_mut().find(|seat| seat.color == *color));
+            if let Some(seat) = seat.filter(|_| configuration.bot_replace_dropped) {
+                let difficulty = room.bots.unwrap_or(configuration.bot_difficulty);
+                seat.occupant = LudoSeatOccupant::Bot(difficulty);
+                seat.profile = None;
+                info!("{:?} bot took over seat {:?} of room {}.", difficulty, color, id);
+            }
+        });
+        let Some(ludo_match) = ludo_match.filter(|_| room.state == LudoGameState::InGame) else { continue };
+        let dropped = ludo_match.seats.iter().filter(|seat| seat.client_id().is_some_and(|client_id| room.members.contains(&client_id) && pool.session(&client_id).is_none())).filter_map(|seat| {
+            seat.profile.as_ref().map(|profile| LudoSeatReservation { unique_id: profile.unique_id, color: seat.color, expires_at: Instant::now() + configuration.reconnect_grace() })
+        }).collect::<Vec<LudoSeatReservation>>();
+        dropped.into_iter().for_each(|reservation| {
+            info!("seat {:?} of room {} is reserved for {}s.", reservation.color, id, configuration.reconnect_grace().as_secs());
+            room.reservations.push(reservation);
+        });
+    }
+}
+
+/* puts a returning profile back on its reserved seat and sends it the state of the match. */
+pub fn rejoin_reserved_seat_system(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeProfilePacket>>,
+    mut rooms: Query<(&mut LudoRoom, &mut LudoServerMatch, &LudoDiceService)>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, .. } in events.read() {
+        /* only profiles accepted by the profile handler are on the session. */
+        let Some(unique_id) = pool.session(client_id).filter(|session| session.room.is_none()).and_then(|session| session.profile.as_ref()).map(|profile| profile.unique_id) else { continue };
+        for (mut room, mut ludo_match, dice) in rooms.iter_mut() {
+            let Some(position) = room.reservations.iter().position(|reservation| reservation.unique_id == unique_id) else { continue };
+            let color = room.reservations.remove(position).color;
+            if let Some(seat) = ludo_match.seats.iter_mut().find(|seat| seat.color == color) {
+                seat.occupant = LudoSeatOccupant::Client(*client_id);
+            }
+            room::join(&mut room, *client_id, &mut pool, &mut server);
+            if let Some(session) = pool.session_mut(client_id) {
+                session.color = Some(color);
+            }
+            pool.send_packet(*client_id, ludo_match.snapshot(&room, Some(color), dice), &mut server);
+            info!("client {} resumed seat {:?} in room {}.", client_id, color, room.id);
+            break;
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::time::Instant;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_renet::renet::{ClientId, RenetServer};
+use ludo_commons::bot::LudoBotDifficulty;
+use ludo_commons::game::{LudoGameConfiguration, LudoGameState, LudoRoomId, LudoRoomInfo};
+use ludo_commons::rules::{LudoColor, LudoRuleSet};
+use ludo_commons::packets::{LudoGameIncomeCreateRoomPacket, LudoGameIncomeJoinRoomPacket, LudoGameIncomeLeaveRoomPacket, LudoGameIncomeListRoomsPacket, LudoGameIncomeSpectateRoomPacket, LudoGameOutcomeRoomJoinedPacket, LudoGameOutcomeRoomListPacket, LudoGameOutcomeRoomRejectedPacket, LudoGameOutcomeServerDataPacket};
+use ludo_commons::{LudoPacket, LudoServerMessage};
+use crate::configuration::LudoServerConfiguration;
+use crate::dice::LudoDiceConfiguration;
+use crate::dice::LudoDiceService;
+use crate::game::{self, LudoServerMatch};
+use crate::handler::LudoIncomePacketEvent;
+use crate::server::LudoOnlineClientPool;
+
+/* one independent match with its own players and rules, the match itself lives as components on the same entity. */
+#[derive(Component, Debug, Clone)]
+pub struct LudoRoom {
+    pub id: LudoRoomId,
+    pub name: String,
+    pub state: LudoGameState,
+    pub min_players: u8,
+    pub max_players: u8,
+    pub rules: LudoRuleSet,
+    pub bots: Option<LudoBotDifficulty>,
+    pub members: Vec<ClientId>,
+    pub spectators: Vec<ClientId>,
+    /* seats of disconnected players which may still come back. */
+    pub reservations: Vec<LudoSeatReservation>,
+    /* when a waiting room with enough members starts its match if it does not fill up before. */
+    pub starts_at: Option<Instant>,
+}
+
+/* seat of a player who dropped out of a running match, kept for the reconnect grace period. */
+#[derive(Debug, Clone)]
+pub struct LudoSeatReservation {
+    pub unique_id: [u8; 16],
+    pub color: LudoColor,
+    pub expires_at: Instant,
+}
+
+#[derive(Resource, Default)]
+pub struct LudoRoomIndex {
+    pub next_id: LudoRoomId,
+    pub rooms: HashMap<LudoRoomId, Entity>,
+}
+
+impl LudoRoom {
+    pub fn info(&self) -> LudoRoomInfo {
+        LudoRoomInfo::new(self.id, self.name.clone(), self.state.clone(), self.members.len() as u8, self.spectators.len() as u8, self.min_players, self.max_players, self.rules.clone(), self.bots)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= self.max_players as usize
+    }
+
+    pub fn broadcast<T>(&self, packet: T, pool: &LudoOnlineClientPool, server: &mut ResMut<RenetServer>) where T: LudoPacket + Into<LudoServerMessage> + Clone {
+        self.members.iter().chain(self.spectators.iter()).for_each(|client| {
+            pool.send_packet(*client, packet.clone(), server);
+        });
+    }
+}
+
+impl LudoRoomIndex {
+    pub fn entity_of(&self, pool: &LudoOnlineClientPool, client: &ClientId) -> Option<Entity> {
+        pool.session(client).and_then(|session| session.room).and_then(|room| self.rooms.get(&room).copied())
+    }
+
+    /* spawns a waiting room with the given clients as its first members, without a start time it waits for the start delay. */
+    #[allow(clippy::too_many_arguments)]
+    pub fn open(&mut self, commands: &mut Commands, name: String, min_players: u8, max_players: u8, rules: LudoRuleSet, bots: Option<LudoBotDifficulty>, starts_at: Option<Instant>, members: &[ClientId], pool: &mut LudoOnlineClientPool, server: &mut ResMut<RenetServer>) -> LudoRoomInfo {
+        self.next_id += 1;
+        let mut room = LudoRoom {
+            id: self.next_id,
+            name,
+            state: LudoGameState::Waiting,
+            min_players,
+            max_players,
+            rules,
+            bots,
+            members: vec![],
+            spectators: vec![],
+            reservations: vec![],
+            starts_at,
+        };
+        info!("room {} \"{}\" opened.", room.id, room.name);
+        members.iter().for_each(|client| join(&mut room, *client, pool, server));
+        let info = room.info();
+        self.rooms.insert(room.id, commands.spawn(room).id());
+        info
+    }
+}
+
+/* a room request is only accepted from admitted clients which are not in another room. */
+pub fn check_lobby_client(pool: &LudoOnlineClientPool, client_id: &ClientId) -> Result<(), String> {
+    match pool.session(client_id) {
+        Some(session) if !session.is_admitted() => Err("the handshake is not finished yet!".to_string()),
+        Some(session) if session.room.is_some() => Err("you are already in a room!".to_string()),
+        Some(_) => Ok(()),
+        None => Err("unknown client!".to_string()),
+    }
+}
+
+pub fn join(room: &mut LudoRoom, client_id: ClientId, pool: &mut LudoOnlineClientPool, server: &mut ResMut<RenetServer>) {
+    room.members.push(client_id);
+    if let Some(session) = pool.session_mut(&client_id) {
+        session.room = Some(room.id);
+    }
+    pool.send_packet(client_id, LudoGameOutcomeRoomJoinedPacket::new(room.info()), server);
+    pool.send_packet(client_id, LudoGameOutcomeServerDataPacket::new(room.min_players.into(), room.rules.clone()), server);
+    info!("client {} joined room {} ({}/{}).", client_id, room.id, room.members.len(), room.max_players);
+}
+
+pub fn handle_list_rooms_packet(mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeListRoomsPacket>>, rooms: Query<&LudoRoom>, pool: Res<LudoOnlineClientPool>, mut server: ResMut<RenetServer>) {
+    for LudoIncomePacketEvent { client_id, .. } in events.read() {
+        let mut list = rooms.iter().map(|room| room.info()).collect::<Vec<LudoRoomInfo>>();
+        list.sort_by_key(|room| room.id);
+        pool.send_packet(*client_id, LudoGameOutcomeRoomListPacket::new(list), &mut server);
+    }
+}
+
+pub fn handle_create_room_packet(
+    mut commands: Commands,
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeCreateRoomPacket>>,
+    mut index: ResMut<LudoRoomIndex>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    configuration: Res<LudoGameConfiguration>,
+    server_configuration: Res<LudoServerConfiguration>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, packet } in events.read() {
+        let min_players = packet.min_players.unwrap_or(configuration.min_players_to_start);
+        let max_players = packet.max_players.unwrap_or(configuration.max_players_to_start);
+        let rules = packet.rules.clone().unwrap_or(configuration.rules.clone());
+        let checked = check_lobby_client(&pool, client_id).and_then(|_| {
+            if index.rooms.len() >= server_configuration.max_rooms {
+                Err("the server can not host any more rooms!".to_string())
+            } else if min_players < 1 || min_players > max_players || max_players as usize > LudoColor::ALL.len() {
+                Err(format!("invalid player limits {}..={}!", min_players, max_players))
+            } else {
+                rules.validate().map_err(|error| error.to_string())
+            }
+        });
+        if let Err(reason) = checked {
+            pool.send_packet(*client_id, LudoGameOutcomeRoomRejectedPacket::new(reason), &mut server);
+            continue;
+        }
+        let room = index.open(&mut commands, packet.name.clone(), min_players, max_players, rules, packet.bots, None, &[*client_id], &mut pool, &mut server);
+        info!("client {} created room {}.", client_id, room.id);
+    }
+}
+
+pub fn handle_join_room_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeJoinRoomPacket>>,
+    index: Res<LudoRoomIndex>,
+    mut rooms: Query<&mut LudoRoom>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, packet } in events.read() {
+        let room = index.rooms.get(&packet.room).and_then(|entity| rooms.get_mut(*entity).ok());
+        let checked = check_lobby_client(&pool, client_id).and_then(|_| match &room {
+            None => Err(format!("room {} does not exist!", packet.room)),
+            Some(room) if room.state != LudoGameState::Waiting => Err("the game is already running!".to_string()),
+            Some(room) if room.is_full() => Err("the game is already full!".to_string()),
+            Some(_) => Ok(()),
+        });
+        match (checked, room) {
+            (Ok(()), Some(mut room)) => join(&mut room, *client_id, &mut pool, &mut server),
+            (Err(reason), _) => pool.send_packet(*client_id, LudoGameOutcomeRoomRejectedPacket::new(reason), &mut server),
+            /* a missing room is already rejected by the check. */
+            (Ok(()), None) => {}
+        }
+    }
+}
+
+pub fn handle_spectate_room_packet(
+    mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeSpectateRoomPacket>>,
+    index: Res<LudoRoomIndex>,
+    mut rooms: Query<(&mut LudoRoom, Option<&LudoServerMatch>, Option<&LudoDiceService>)>,
+    configuration: Res<LudoServerConfiguration>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for LudoIncomePacketEvent { client_id, packet } in events.read() {
+        let room = index.rooms.get(&packet.room).and_then(|entity| rooms.get_mut(*entity).ok());
+        let checked = check_lobby_client(&pool, client_id).and_then(|_| match &room {
+            None => Err(format!("room {} does not exist!", packet.room)),
+            Some((room, _, _)) if room.spectators.len() >= configuration.max_spectators_per_room => Err("the room has no place for more spectators!".to_string()),
+            Some(_) => Ok(()),
+        });
+        match (checked, room) {
+            (Ok(()), Some((mut room, ludo_match, dice))) => {
+                room.spectators.push(*client_id);
+                if let Some(session) = pool.session_mut(client_id) {
+                    session.room = Some(room.id);
+                    session.spectating = true;
+                }
+                pool.send_packet(*client_id, LudoGameOutcomeRoomJoinedPacket::new(room.info()), &mut server);
+                pool.send_packet(*client_id, LudoGameOutcomeServerDataPacket::new(room.min_players.into(), room.rules.clone()), &mut server);
+                /* a running match is shown right away, everything after it arrives with the broadcasts. */
+                if let (Some(ludo_match), Some(dice)) = (ludo_match, dice) {
+                    pool.send_packet(*client_id, ludo_match.snapshot(&room, None, dice), &mut server);
+                }
+                info!("client {} spectates room {} ({} spectators).", client_id, room.id, room.spectators.len());
+            }
+            (Err(reason), _) => pool.send_packet(*client_id, LudoGameOutcomeRoomRejectedPacket::new(reason), &mut server),
+            (Ok(()), None) => {}
+        }
+    }
+}
+
+pub fn handle_leave_room_packet(mut events: EventReader<LudoIncomePacketEvent<LudoGameIncomeLeaveRoomPacket>>, mut pool: ResMut<LudoOnlineClientPool>) {
+    for LudoIncomePacketEvent { client_id, .. } in events.read() {
+        if let Some(session) = pool.session_mut(client_id) {
+            if let Some(room) = session.room.take() {
+                session.color = None;
+                session.spectating = false;
+                info!("client {} left room {}.", client_id, room);
+            }
+        }
+    }
+}
+
+/* drops members which left or disconnected, closes empty rooms without reserved seats and starts the match of full rooms
+   or of rooms which had enough members for the start delay. */
+pub fn update_rooms_system(
+    mut commands: Commands,
+    mut index: ResMut<LudoRoomIndex>,
+    mut rooms: Query<(Entity, &mut LudoRoom)>,
+    mut pool: ResMut<LudoOnlineClientPool>,
+    dice_configuration: Res<LudoDiceConfiguration>,
+    configuration: Res<LudoServerConfiguration>,
+    mut server: ResMut<RenetServer>
+) {
+    for (entity, mut room) in rooms.iter_mut() {
+        let id = room.id;
+        room.members.retain(|client| pool.session(client).is_some_and(|session| session.room == Some(id)));
+        room.spectators.retain(|client| pool.session(client).is_some_and(|session| session.room == Some(id)));
+        if room.members.is_empty() && room.reservations.is_empty() {
+            /* spectators are back in the lobby once nobody plays anymore. */
+            room.spectators.iter().for_each(|client| {
+                if let Some(session) = pool.session_mut(client) {
+                    session.room = None;
+                    session.spectating = false;
+                }
+                pool.send_packet(*client, LudoGameOutcomeRoomRejectedPacket::new("the room was closed!".to_string()), &mut server);
+            });
+            commands.entity(entity).despawn();
+            index.rooms.remove(&id);
+            info!("room {} closed.", id);
+        } else if room.state == LudoGameState::Waiting {
+            if room.members.len() < room.min_players as usize {
+                room.starts_at = None;
+            } else if room.is_full() || room.starts_at.is_some_and(|starts_at| starts_at <= Instant::now()) {
+                room.starts_at = None;
+                game::start_match(&mut commands, entity, &mut room, &mut pool, &dice_configuration, &configuration, &mut server);
+            } else if room.starts_at.is_none() {
+                room.starts_at = Some(Instant::now() + configuration.room_start_delay());
+                info!("room {} starts in {:?} unless it fills up before.", id, configuration.room_start_delay());
+            }
+        }
+    }
+}
@@ -1,22 +1,28 @@
 use bevy_renet::netcode::NetcodeServerTransport;
 use bevy_renet::netcode::ServerAuthentication;
 use bevy_renet::netcode::ServerConfig;
-use std::any::Any;
 use std::net::UdpSocket;
-pub use std::time::{Duration, SystemTime};
+use std::time::Instant;
+pub use std::time::SystemTime;
 use bevy::prelude::*;
-use bevy::reflect::erased_serde::__private::serde::de::DeserializeOwned;
-use bevy::reflect::erased_serde::Serialize;
 use bevy::utils::HashMap;
 use bevy_renet::renet::{ClientId, ConnectionConfig, DefaultChannel, RenetServer, ServerEvent};
 use derive_new::new;
-use ludo_commons::{LudoPacket, Pair};
-use ludo_commons::game::{LudoGameConfiguration, LudoGameObject, LudoGameProfile, LudoGameProfileData, LudoGameState};
-use ludo_commons::packets::LudoGameOutcomeGameStartPacket;
-use crate::{backup, communication, handler, handshake};
-use crate::backup::LudoBackupProfileTimer;
+use ludo_commons::{protocol, security, LudoMessage, LudoPacket, LudoServerMessage};
+use ludo_commons::game::{LudoGameConfiguration, LudoGameProfile};
+use ludo_commons::packets::{LudoGameIncomeCancelMatchPacket, LudoGameIncomeSpectateRoomPacket, LudoGameIncomeCreateRoomPacket, LudoGameIncomeFindMatchPacket, LudoGameIncomeJoinRoomPacket, LudoGameIncomeLeaveRoomPacket, LudoGameIncomeListRoomsPacket, LudoGameOutcomeHandshakeChallengePacket, LudoGameIncomeHandshakePacket, LudoGameIncomeMovePacket, LudoGameIncomeProfilePacket, LudoGameIncomeRollPacket, LudoGameIncomeStatisticsPacket};
+use ludo_commons::rules::LudoRuleSet;
+use crate::{backup, bot, communication, game, handler, handshake, matchmaking, reconnect, room, turn};
+use crate::backup::{LudoBackupProfileTimer, LudoSnapshotTimer};
 use crate::communication::LudoProfilesInfoTimer;
-use crate::handshake::HandshakeTimer;
+use crate::game::LudoPlacementPoints;
+use crate::handler::LudoIncomePacketApp;
+use crate::configuration::LudoServerConfiguration;
+use crate::session::LudoClientSession;
+use crate::handshake::{HandshakeTimer, LudoHandshakeAttempts, LudoHandshakeAttemptsTimer, LudoHandshakeKey};
+use crate::matchmaking::LudoMatchmakingQueue;
+use crate::room::LudoRoomIndex;
+use crate::store::LudoProfiles;
 
 #[derive(Default)]
 pub struct LudoServerPlugin {
@@ -24,14 +30,32 @@ pub struct LudoServerPlugin {
 
 #[derive(Resource, Default)]
 pub struct LudoOnlineClientPool {
-    pub ludo_clients_pool: HashMap<ClientId, Vec<Pair<String, Box<dyn Any + Sync + Send>>>>,
+    pub sessions: HashMap<ClientId, LudoClientSession>,
 }
 
 impl Plugin for LudoServerPlugin {
     fn build(&self, application: &mut App) {
+        let configuration = application.world().get_resource::<LudoServerConfiguration>().cloned().unwrap_or_default();
         application
-            .init_state::<LudoGameState>()
-            .insert_resource(LudoGameConfiguration::new(1, 4))
+            .insert_resource(LudoGameConfiguration::new(configuration.min_players_to_start, configuration.max_players_to_start, LudoRuleSet::default()))
+            .insert_resource(configuration.dice_configuration().expect("the dice seed is validated with the configuration"))
+            .insert_resource(LudoPlacementPoints(configuration.placement_points.iter().map(|points| *points as u128).collect()))
+            .insert_resource(LudoHandshakeAttempts::new(configuration.handshake_block()))
+            .insert_resource(configuration)
+            .init_resource::<LudoRoomIndex>()
+            .init_resource::<LudoMatchmakingQueue>()
+            .add_income_packet::<LudoGameIncomeHandshakePacket>()
+            .add_income_packet::<LudoGameIncomeProfilePacket>()
+            .add_income_packet::<LudoGameIncomeRollPacket>()
+            .add_income_packet::<LudoGameIncomeMovePacket>()
+            .add_income_packet::<LudoGameIncomeListRoomsPacket>()
+            .add_income_packet::<LudoGameIncomeCreateRoomPacket>()
+            .add_income_packet::<LudoGameIncomeJoinRoomPacket>()
+            .add_income_packet::<LudoGameIncomeLeaveRoomPacket>()
+            .add_income_packet::<LudoGameIncomeSpectateRoomPacket>()
+            .add_income_packet::<LudoGameIncomeFindMatchPacket>()
+            .add_income_packet::<LudoGameIncomeCancelMatchPacket>()
+            .add_income_packet::<LudoGameIncomeStatisticsPacket>()
             .add_systems(PreStartup, Self::enable_system)
             .add_systems(Startup, Self::enable_listener_system)
             .add_systems(
@@ -39,41 +63,68 @@ impl Plugin for LudoServerPlugin {
                 (
                     Self::connect_listener,
                     handshake::update_handshake_timer,
+                    handshake::prune_handshake_attempts,
                     handler::handle_client_income,
+                    (handler::handle_handshake_packet, handler::handle_profile_packet).after(handler::handle_client_income),
+                    handler::handle_statistics_packet.after(handler::handle_profile_packet),
                     Self::disable_application_system,
                     backup::handle_backup_profile_timer,
-                    communication::handle_client_outcome_profiles_info,
-                    Self::ludo_game_update.run_if(in_state(LudoGameState::Waiting))
+                    backup::handle_snapshot_timer,
+                    communication::handle_client_outcome_profiles_info
                 )
+            )
+            .add_systems(
+                Update,
+                (
+                    (reconnect::reserve_dropped_seats_system, reconnect::rejoin_reserved_seat_system).chain(),
+                    (room::handle_list_rooms_packet, room::handle_create_room_packet, room::handle_join_room_packet, room::handle_spectate_room_packet, room::handle_leave_room_packet).chain(),
+                    (matchmaking::handle_find_match_packet, matchmaking::handle_cancel_match_packet, matchmaking::update_matchmaking_system).chain(),
+                    room::update_rooms_system,
+                    game::handle_turn_roll_system,
+                    game::handle_turn_move_system,
+                    game::handle_turn_absent_system,
+                    bot::handle_bot_turn_system,
+                    turn::update_turn_timer,
+                    game::handle_game_over_system,
+                    game::update_closing_timer
+                ).chain().after(handler::handle_profile_packet).after(Self::connect_listener)
             );
     }
 }
 
 impl LudoServerPlugin {
-    pub fn enable_system(mut commands: Commands) {
-        let result = LudoGameProfileData::load_from_file("profiles.json").expect("unable to load ludo game profiles");
-        info!("load game profiles...");
-        result.iter().for_each(|profile| {
-            let info = commands.spawn(profile.clone()).id();
-            info!("loaded profile to the cache: [{}].", info);
-        });
-        commands.insert_resource(LudoGameObject { state: LudoGameState::Waiting });
-        commands.spawn(LudoBackupProfileTimer(Timer::new(Duration::from_secs(9), TimerMode::Repeating)));
-        commands.spawn(LudoProfilesInfoTimer(Timer::new(Duration::from_secs(2), TimerMode::Repeating)));
+    pub fn enable_system(mut commands: Commands, mut configuration: ResMut<LudoGameConfiguration>, server_configuration: Res<LudoServerConfiguration>) {
+        configuration.rules = LudoRuleSet::load_from_file(&server_configuration.rules_file).expect("unable to load ludo rule set");
+        info!("loaded ludo rule set: {:?}", configuration.rules);
+        let profiles = LudoProfiles::open(&server_configuration).expect("unable to open ludo game profiles");
+        info!("opened {:?} profile store.", server_configuration.profile_store);
+        match profiles.0.top(3) {
+            Ok(best) => best.iter().enumerate().for_each(|(place, profile)| info!("leaderboard {}: {:?} with {} points.", place + 1, profile.unique_id, profile.points)),
+            Err(error) => error!("unable to read the leaderboard: {}", error),
+        }
+        commands.insert_resource(profiles);
+        commands.spawn(LudoBackupProfileTimer(Timer::new(server_configuration.backup_interval(), TimerMode::Repeating)));
+        commands.spawn(LudoSnapshotTimer(Timer::new(server_configuration.snapshot_interval(), TimerMode::Repeating)));
+        commands.spawn(LudoHandshakeAttemptsTimer(Timer::new(server_configuration.handshake_block(), TimerMode::Repeating)));
+        commands.spawn(LudoProfilesInfoTimer(Timer::new(server_configuration.profiles_broadcast_interval(), TimerMode::Repeating)));
     }
 
-    pub fn enable_listener_system(mut commands: Commands) {
-        let address = "127.0.0.1:2000".parse().unwrap();
+    pub fn enable_listener_system(mut commands: Commands, server_configuration: Res<LudoServerConfiguration>) {
+        let address = server_configuration.address;
         let renet_server_interface = RenetServer::new(ConnectionConfig::default());
         commands.insert_resource(renet_server_interface);
 
+        let private_key = security::load_or_generate_key(&server_configuration.private_key_file).expect("unable to load private key");
+        let handshake_key = security::load_or_generate_key(&server_configuration.handshake_key_file).expect("unable to load handshake key");
+        commands.insert_resource(LudoHandshakeKey(handshake_key));
+
         let udp_server_interface = UdpSocket::bind(address).unwrap();
         let udp_server_config = ServerConfig {
             current_time: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(),
-            max_clients: 6,
-            protocol_id: 0,
-            public_addresses: vec![address],
-            authentication: ServerAuthentication::Unsecure,
+            max_clients: server_configuration.max_clients,
+            protocol_id: protocol::LUDO_PROTOCOL_ID,
+            public_addresses: server_configuration.public_addresses(),
+            authentication: ServerAuthentication::Secure { private_key },
         };
 
         let transport = NetcodeServerTransport::new(udp_server_config, udp_server_interface).unwrap();
@@ -88,107 +139,62 @@ impl LudoServerPlugin {
         mut server_event: EventReader<ServerEvent>,
         mut client_pool: ResMut<LudoOnlineClientPool>,
         server_transport: Res<NetcodeServerTransport>,
-        game_object: Res<LudoGameObject>,
-        configuration: Res<LudoGameConfiguration>,
+        handshake_attempts: Res<LudoHandshakeAttempts>,
+        server_configuration: Res<LudoServerConfiguration>,
         mut server: ResMut<RenetServer>
     ) {
         for server_event in server_event.read() {
             match server_event {
                 ServerEvent::ClientConnected { client_id } => {
-                    if game_object.state == LudoGameState::Waiting {
-                        if client_pool.ludo_clients_pool.len() < configuration.max_players_to_start as usize {
-                            client_pool.ludo_clients_pool.insert(*client_id, Vec::new());
-                            if let Some(address) = server_transport.client_addr(client_id.clone()) {
-                                info!("new client connected id: {0}, address: {1}", client_id, address);
-                            }
-                            client_pool.ludo_clients_pool.get_mut(&*client_id).unwrap().push(Pair::new("client.handshake".to_string(), Box::new(false)));
-                            commands.spawn(HandshakeTimer(Timer::new(Duration::from_millis(500), TimerMode::Once), client_id.clone()));
-                        } else {
-                            server.disconnect(client_id.clone());
-                        }
+                    if server_transport.client_addr(*client_id).is_some_and(|address| handshake_attempts.is_blocked(&address.ip(), Instant::now())) {
+                        warn!("refused client {} because of too many failed handshakes", client_id);
+                        server.disconnect(*client_id);
                     } else {
-                        server.disconnect(client_id.clone());
+                        let address = server_transport.client_addr(*client_id);
+                        if let Some(address) = address {
+                            info!("new client connected id: {0}, address: {1}", client_id, address);
+                        }
+                        let nonce = security::generate_nonce();
+                        client_pool.sessions.insert(*client_id, LudoClientSession::new(*client_id, address, nonce));
+                        client_pool.send_packet(*client_id, LudoGameOutcomeHandshakeChallengePacket::new(nonce), &mut server);
+                        commands.spawn(HandshakeTimer(Timer::new(server_configuration.handshake_timeout(), TimerMode::Once), client_id.clone()));
                     }
                 }
                 ServerEvent::ClientDisconnected { client_id, reason } => {
-                    client_pool.ludo_clients_pool.remove(*&client_id);
                     info!("client disconnected: {0}. Because of: {1}", client_id, reason);
+                    if let Some(session) = client_pool.sessions.remove(client_id) {
+                        info!("session of {0} lasted {1}s", client_id, session.connected_at.elapsed().unwrap_or_default().as_secs());
+                    }
                 }
             }
         }
     }
 
-    pub fn disable_application_system(mut event_reader: EventReader<AppExit>, profiles: Query<&LudoGameProfileData>) {
-        event_reader.read().for_each(move |_exit_event| {
+    pub fn disable_application_system(mut event_reader: EventReader<AppExit>, mut profiles: ResMut<LudoProfiles>) {
+        event_reader.read().for_each(|_exit_event| {
             info!("exit event.");
-            let mut list = vec![];
-            profiles.iter().for_each(|profile| {
-                list.push(profile.clone());
-            });
-            LudoGameProfileData::load_to_file("profiles.json", list).expect("unable to save ludo game profiles!");
+            profiles.0.flush().expect("unable to save ludo game profiles!");
             info!("disabled ludo game profiles");
         })
     }
 
-    pub fn ludo_game_update(
-        mut commands: Commands,
-        configuration: Res<LudoGameConfiguration>,
-        mut game_object: ResMut<LudoGameObject>,
-        online_profile_pool: Res<LudoOnlineClientPool>,
-        mut server: ResMut<RenetServer>
-    ) {
-        if game_object.state == LudoGameState::Waiting {
-            if online_profile_pool.ludo_clients_pool.len() >= configuration.min_players_to_start as usize {
-                for client in online_profile_pool.ludo_clients_pool.iter() {
-                    let start_packet = LudoGameOutcomeGameStartPacket::new().into_string::<LudoGameOutcomeGameStartPacket>().expect("unable to parse packet into string");
-                    server.send_message(client.0.clone(), DefaultChannel::ReliableOrdered, start_packet);
-                }
-                info!("ludo game is starting...");
-                commands.insert_resource(State::new(LudoGameState::InGame));
-            }
-        }
-    }
-
 }
 
 impl LudoOnlineClientPool {
-    pub fn get_information<T: 'static>(&self, client: &ClientId, info: &str) -> Option<&T> {
-        if let Some(profile_cache) = self.ludo_clients_pool.get(client) {
-            for item in profile_cache {
-                if item.0 == info {
-                    let cast = item.1.as_ref().downcast_ref::<T>().expect("unable to cast target information");
-                    return Some(cast);
-                }
-            }
-            None
-        } else { None }
+    pub fn session(&self, client: &ClientId) -> Option<&LudoClientSession> {
+        self.sessions.get(client)
     }
 
-    pub fn set_information<T>(&mut self, client: &ClientId, info: &str, some: T) where T: Any + Sync + Send {
-        if let Some(profile_cache) = self.ludo_clients_pool.get_mut(client) {
-            let mut i = 0;
-            let mut bool = false;
-            for item in &mut *profile_cache {
-                if item.0 == info {
-                    bool = true;
-                    break;
-                }
-                i+=1;
-            }
-            if bool {
-                profile_cache.insert(i, Pair::new(info.to_string(), Box::new(some)));
-            } else {
-                profile_cache.push(Pair::new(info.to_string(), Box::new(some)));
-            }
-        }
+    pub fn session_mut(&mut self, client: &ClientId) -> Option<&mut LudoClientSession> {
+        self.sessions.get_mut(client)
     }
 
     pub fn is_handshaked(&self, client: &ClientId) -> bool {
-        self.get_information::<bool>(client, "client.handshaked").is_some()
+        self.session(client).is_some_and(|session| session.is_admitted())
     }
 
-    pub fn send_packet<T>(&self, client: ClientId, packet: T, mut server: &mut ResMut<RenetServer>) where T: LudoPacket + Serialize + DeserializeOwned {
-        let packet = packet.into_string::<T>().expect("unable to parse packet into string");
+    pub fn send_packet<T>(&self, client: ClientId, packet: T, mut server: &mut ResMut<RenetServer>) where T: LudoPacket + Into<LudoServerMessage> {
+        let packet = packet.into().encode().expect("unable to encode packet");
         server.send_message(client, DefaultChannel::ReliableOrdered, packet);
     }
 }
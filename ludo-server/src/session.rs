@@ -0,0 +1,58 @@
+use std::net::SocketAddr;
+use std::time::{Instant, SystemTime};
+use bevy_renet::renet::ClientId;
+use ludo_commons::game::{LudoGameProfile, LudoRoomId};
+use ludo_commons::rules::LudoColor;
+use ludo_commons::security::LudoNonce;
+
+/* handshake state machine of one connection: Challenged -> Verified -> Admitted. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LudoHandshakePhase {
+    /* the challenge nonce was sent, no valid response yet. */
+    Challenged,
+    /* the response matched, the client is admitted once its handshake timer runs out. */
+    Verified,
+    /* the client is part of the game and receives the server data. */
+    Admitted,
+}
+
+/* everything the server knows about one connected client. */
+#[derive(Debug, Clone)]
+pub struct LudoClientSession {
+    pub client_id: ClientId,
+    pub address: Option<SocketAddr>,
+    pub phase: LudoHandshakePhase,
+    /* nonce of the handshake challenge, the profile signature covers it as well. */
+    pub nonce: LudoNonce,
+    pub profile: Option<LudoGameProfile>,
+    pub room: Option<LudoRoomId>,
+    /* the room is only watched, without a seat. */
+    pub spectating: bool,
+    pub color: Option<LudoColor>,
+    pub connected_at: SystemTime,
+    pub last_activity: Instant,
+    /* round trip time as reported by renet. */
+    pub latency: Option<f64>,
+}
+
+impl LudoClientSession {
+    pub fn new(client_id: ClientId, address: Option<SocketAddr>, nonce: LudoNonce) -> Self {
+        Self {
+            client_id,
+            address,
+            phase: LudoHandshakePhase::Challenged,
+            nonce,
+            profile: None,
+            room: None,
+            spectating: false,
+            color: None,
+            connected_at: SystemTime::now(),
+            last_activity: Instant::now(),
+            latency: None,
+        }
+    }
+
+    pub fn is_admitted(&self) -> bool {
+        self.phase == LudoHandshakePhase::Admitted
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use anyhow::{anyhow, Result};
+use bevy::prelude::Resource;
+use derive_new::new;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ludo_commons::game::{LudoGameProfileData, LudoGameStatistics, LudoMatchSummary};
+use crate::configuration::LudoServerConfiguration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LudoProfileStoreKind {
+    /* every profile in memory, written to the profiles file on every backup tick. */
+    Json,
+    /* an embedded sqlite database, every change is written right away. */
+    Sqlite,
+}
+
+/* outcome of a finished match for one profile. */
+#[derive(Debug, Clone, new)]
+pub struct LudoMatchResult {
+    pub unique_id: [u8; 16],
+    pub summary: LudoMatchSummary,
+}
+
+/* persistent profile data, the server only asks for the profiles it needs. */
+pub trait LudoProfileStore: Send + Sync {
+    fn get(&self, unique_id: &[u8; 16]) -> Result<Option<LudoGameProfileData>>;
+
+    fn upsert(&mut self, profile: &LudoGameProfileData) -> Result<()>;
+
+    fn all(&self) -> Result<Vec<LudoGameProfileData>>;
+
+    /* drops every profile in favour of the given ones, used to restore a snapshot. */
+    fn replace_all(&mut self, profiles: &[LudoGameProfileData]) -> Result<()>;
+
+    /* the profiles with the most points, best first. */
+    fn top(&self, count: usize) -> Result<Vec<LudoGameProfileData>>;
+
+    /* adds the points and statistics of a match to every known profile of it and returns their new data. */
+    fn record_match(&mut self, results: &[LudoMatchResult]) -> Result<Vec<LudoGameProfileData>>;
+
+    /* writes pending changes, called by the backup timer and on exit. */
+    fn flush(&mut self) -> Result<()>;
+}
+
+#[derive(Resource)]
+pub struct LudoProfiles(pub Box<dyn LudoProfileStore>);
+
+impl LudoProfiles {
+    pub fn open(configuration: &LudoServerConfiguration) -> Result<LudoProfiles> {
+        Ok(match configuration.profile_store {
+            LudoProfileStoreKind::Json => LudoProfiles(Box::new(LudoJsonProfileStore::open(&configuration.profiles_file)?)),
+            LudoProfileStoreKind::Sqlite => LudoProfiles(Box::new(LudoSqliteProfileStore::open(&configuration.profiles_database)?)),
+        })
+    }
+}
+
+/* the profiles file as the server always wrote it. */
+pub struct LudoJsonProfileStore {
+    file: String,
+    profiles: HashMap<[u8; 16], LudoGameProfileData>,
+    dirty: bool,
+}
+
+impl LudoJsonProfileStore {
+    pub fn open(file: &str) -> Result<LudoJsonProfileStore> {
+        let profiles = LudoGameProfileData::load_from_file(file)?.into_iter().map(|profile| (profile.unique_id, profile)).collect();
+        Ok(LudoJsonProfileStore { file: file.to_string(), profiles, dirty: false })
+    }
+}
+
+impl LudoProfileStore for LudoJsonProfileStore {
+    fn get(&self, unique_id: &[u8; 16]) -> Result<Option<LudoGameProfileData>> {
+        Ok(self.profiles.get(unique_id).cloned())
+    }
+
+    fn upsert(&mut self, profile: &LudoGameProfileData) -> Result<()> {
+        self.profiles.insert(profile.unique_id, profile.clone());
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<LudoGameProfileData>> {
+        Ok(self.profiles.values().cloned().collect())
+    }
+
+    fn replace_all(&mut self, profiles: &[LudoGameProfileData]) -> Result<()> {
+        self.profiles = profiles.iter().map(|profile| (profile.unique_id, profile.clone())).collect();
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn top(&self, count: usize) -> Result<Vec<LudoGameProfileData>> {
+        let mut list = self.profiles.values().cloned().collect::<Vec<LudoGameProfileData>>();
+        list.sort_by_key(|profile| std::cmp::Reverse(profile.points));
+        list.truncate(count);
+        Ok(list)
+    }
+
+    fn record_match(&mut self, results: &[LudoMatchResult]) -> Result<Vec<LudoGameProfileData>> {
+        let updated = results.iter().filter_map(|result| {
+            self.profiles.get_mut(&result.unique_id).map(|profile| {
+                profile.points += result.summary.points;
+                profile.statistics.record(result.summary.clone());
+                profile.clone()
+            })
+        }).collect::<Vec<LudoGameProfileData>>();
+        self.dirty = true;
+        Ok(updated)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            LudoGameProfileData::load_to_file(&self.file, self.profiles.values().cloned().collect())?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/* points are stored as 16 big endian bytes, sqlite compares blobs bytewise so they still sort by value. Statistics are
+   stored as json. A connection can not be shared between threads, bevy resources have to be. */
+pub struct LudoSqliteProfileStore {
+    connection: Mutex<Connection>,
+}
+
+impl LudoSqliteProfileStore {
+    pub fn open(file: &str) -> Result<LudoSqliteProfileStore> {
+        Self::from_connection(Connection::open(file)?)
+    }
+
+    /* creates the tables of a fresh database and migrates an older one. */
+    pub fn from_connection(connection: Connection) -> Result<LudoSqliteProfileStore> {
+        connection.execute_batch("
+            PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS profiles (
+                unique_id BLOB PRIMARY KEY NOT NULL,
+                points BLOB NOT NULL,
+                public_key BLOB
+            );
+            CREATE INDEX IF NOT EXISTS profiles_points ON profiles (points DESC);
+        ")?;
+        /* the layout of the database as counted by its user version, every step lifts it by one. */
+        let version = connection.query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))?;
+        if version < 1 {
+            connection.execute_batch("
+                ALTER TABLE profiles ADD COLUMN statistics TEXT;
+                PRAGMA user_version = 1;
+            ")?;
+        }
+        Ok(LudoSqliteProfileStore { connection: Mutex::new(connection) })
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.connection.lock().map_err(|_| anyhow!("the profiles database connection is poisoned"))
+    }
+
+    fn profile_of_row(row: &rusqlite::Row) -> rusqlite::Result<LudoGameProfileData> {
+        let statistics = row.get::<_, Option<String>>(3)?.map(|statistics| serde_json::from_str::<LudoGameStatistics>(&statistics)).transpose().map_err(|error| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(error)))?;
+        Ok(LudoGameProfileData {
+            unique_id: row.get(0)?,
+            points: u128::from_be_bytes(row.get(1)?),
+            public_key: row.get(2)?,
+            statistics: statistics.unwrap_or_default(),
+        })
+    }
+}
+
+impl LudoProfileStore for LudoSqliteProfileStore {
+    fn get(&self, unique_id: &[u8; 16]) -> Result<Option<LudoGameProfileData>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare_cached("SELECT unique_id, points, public_key, statistics FROM profiles WHERE unique_id = ?1")?;
+        Ok(statement.query_row(params![unique_id], Self::profile_of_row).optional()?)
+    }
+
+    fn upsert(&mut self, profile: &LudoGameProfileData) -> Result<()> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare_cached("INSERT INTO profiles (unique_id, points, public_key, statistics) VALUES (?1, ?2, ?3, ?4) ON CONFLICT (unique_id) DO UPDATE SET points = excluded.points, public_key = excluded.public_key, statistics = excluded.statistics")?;
+        statement.execute(params![profile.unique_id, profile.points.to_be_bytes(), profile.public_key, serde_json::to_string(&profile.statistics)?])?;
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<LudoGameProfileData>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare_cached("SELECT unique_id, points, public_key, statistics FROM profiles")?;
+        let rows = statement.query_map([], Self::profile_of_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<LudoGameProfileData>>>()?)
+    }
+
+    fn replace_all(&mut self, profiles: &[LudoGameProfileData]) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM profiles", [])?;
+        for profile in profiles {
+            transaction.execute("INSERT INTO profiles (unique_id, points, public_key, statistics) VALUES (?1, ?2, ?3, ?4)", params![profile.unique_id, profile.points.to_be_bytes(), profile.public_key, serde_json::to_string(&profile.statistics)?])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn top(&self, count: usize) -> Result<Vec<LudoGameProfileData>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare_cached("SELECT unique_id, points, public_key, statistics FROM profiles ORDER BY points DESC LIMIT ?1")?;
+        let rows = statement.query_map(params![count as i64], Self::profile_of_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<LudoGameProfileData>>>()?)
+    }
+
+    fn record_match(&mut self, results: &[LudoMatchResult]) -> Result<Vec<LudoGameProfileData>> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        let mut updated = vec![];
+        for result in results {
+            let profile = transaction.query_row("SELECT unique_id, points, public_key, statistics FROM profiles WHERE unique_id = ?1", params![result.unique_id], Self::profile_of_row).optional()?;
+            if let Some(mut profile) = profile {
+                profile.points += result.summary.points;
+                profile.statistics.record(result.summary.clone());
+                transaction.execute("UPDATE profiles SET points = ?2, statistics = ?3 WHERE unique_id = ?1", params![profile.unique_id, profile.points.to_be_bytes(), serde_json::to_string(&profile.statistics)?])?;
+                updated.push(profile);
+            }
+        }
+        transaction.commit()?;
+        Ok(updated)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(seed: u8, points: u128) -> LudoGameProfileData {
+        LudoGameProfileData { unique_id: [seed; 16], points, public_key: Some([seed; 32]), statistics: LudoGameStatistics::default() }
+    }
+
+    fn summary(place: u8, points: u128) -> LudoMatchSummary {
+        LudoMatchSummary::new(1_700_000_000, place, 2, points, 1, 2, 3, 600)
+    }
+
+    fn sorted(mut profiles: Vec<LudoGameProfileData>) -> Vec<LudoGameProfileData> {
+        profiles.sort_by_key(|profile| profile.unique_id);
+        profiles
+    }
+
+    fn memory_store() -> LudoSqliteProfileStore {
+        LudoSqliteProfileStore::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn databases_without_statistics_are_migrated() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE profiles (unique_id BLOB PRIMARY KEY NOT NULL, points BLOB NOT NULL, public_key BLOB);").unwrap();
+        connection.execute("INSERT INTO profiles (unique_id, points, public_key) VALUES (?1, ?2, NULL)", params![[4u8; 16], 42u128.to_be_bytes()]).unwrap();
+        let store = LudoSqliteProfileStore::from_connection(connection).unwrap();
+        let version = store.connection().unwrap().query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0)).unwrap();
+        assert_eq!(version, 1);
+        let migrated = store.get(&[4; 16]).unwrap().unwrap();
+        assert_eq!((migrated.points, migrated.public_key, migrated.statistics), (42, None, LudoGameStatistics::default()));
+    }
+
+    #[test]
+    fn points_round_trip_as_big_endian_blobs() {
+        let mut store = memory_store();
+        let points = [0, 1, 255, 256, u64::MAX as u128, u64::MAX as u128 + 1, u128::MAX];
+        for (seed, points) in points.iter().enumerate() {
+            store.upsert(&profile(seed as u8, *points)).unwrap();
+        }
+        for (seed, points) in points.iter().enumerate() {
+            assert_eq!(store.get(&[seed as u8; 16]).unwrap().unwrap().points, *points);
+        }
+        let best = store.top(points.len()).unwrap().iter().map(|profile| profile.points).collect::<Vec<u128>>();
+        assert_eq!(best, points.iter().rev().copied().collect::<Vec<u128>>());
+    }
+
+    #[test]
+    fn json_and_sqlite_stores_agree() {
+        let path = std::env::temp_dir().join(format!("ludo-{}-store-parity.json", std::process::id()));
+        let file = path.to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(ludo_commons::storage::backup_path(&file));
+        let mut stores: Vec<Box<dyn LudoProfileStore>> = vec![Box::new(LudoJsonProfileStore::open(&file).unwrap()), Box::new(memory_store())];
+        for store in stores.iter_mut() {
+            store.upsert(&profile(1, 10)).unwrap();
+            store.upsert(&profile(2, u64::MAX as u128 + 5)).unwrap();
+            store.upsert(&profile(3, 0)).unwrap();
+            let updated = store.record_match(&[LudoMatchResult::new([1; 16], summary(1, 100)), LudoMatchResult::new([3; 16], summary(2, 50)), LudoMatchResult::new([9; 16], summary(1, 100))]).unwrap();
+            assert_eq!(updated.len(), 2);
+            store.flush().unwrap();
+        }
+        let reopened = LudoJsonProfileStore::open(&file).unwrap();
+        let sqlite = &stores[1];
+        assert_eq!(sorted(reopened.all().unwrap()), sorted(sqlite.all().unwrap()));
+        assert_eq!(reopened.top(2).unwrap(), sqlite.top(2).unwrap());
+        assert_eq!(reopened.get(&[1; 16]).unwrap().unwrap().statistics.wins, 1);
+        assert_eq!(sqlite.get(&[3; 16]).unwrap().unwrap().statistics.losses, 1);
+        assert!(sqlite.get(&[9; 16]).unwrap().is_none());
+        for store in stores.iter_mut() {
+            store.replace_all(&[profile(7, 70)]).unwrap();
+            store.flush().unwrap();
+        }
+        assert_eq!(LudoJsonProfileStore::open(&file).unwrap().all().unwrap(), stores[1].all().unwrap());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(ludo_commons::storage::backup_path(&file));
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use ludo_commons::bot::{self, LudoBotDifficulty};
+use ludo_commons::game::LudoGameState;
+use ludo_commons::packets::{LudoGameOutcomeDisconnectPacket, LudoGameOutcomeTurnDeadlinePacket};
+use crate::configuration::LudoServerConfiguration;
+use crate::dice::LudoDiceService;
+use crate::game::{self, LudoSeatOccupant, LudoServerMatch, LudoTurnPhase};
+use crate::room::LudoRoom;
+use crate::server::LudoOnlineClientPool;
+
+/* deadline of the next action in a room, restarted whenever the match steps forward. */
+#[derive(Component)]
+pub struct LudoTurnTimer(pub Timer, pub u32);
+
+impl LudoTurnTimer {
+    /* matches no step of the match, so its first frame restarts the deadline and announces it to the first player. */
+    pub fn new(timeout: Duration) -> Self {
+        LudoTurnTimer(Timer::new(timeout, TimerMode::Once), u32::MAX)
+    }
+}
+
+/* restarts the deadline after every step and acts for a player whose time is up, players timing out too often are kicked. */
+pub fn update_turn_timer(
+    time: Res<Time>,
+    mut rooms: Query<(&LudoRoom, &mut LudoServerMatch, &mut LudoDiceService, &mut LudoTurnTimer)>,
+    configuration: Res<LudoServerConfiguration>,
+    pool: Res<LudoOnlineClientPool>,
+    mut server: ResMut<RenetServer>
+) {
+    for (room, mut ludo_match, mut dice, mut timer) in rooms.iter_mut() {
+        if room.state != LudoGameState::InGame {
+            continue;
+        }
+        let LudoSeatOccupant::Client(client_id) = ludo_match.active().occupant else { continue };
+        if timer.1 != ludo_match.steps {
+            timer.0.reset();
+            timer.1 = ludo_match.steps;
+            room.broadcast(LudoGameOutcomeTurnDeadlinePacket::new(ludo_match.active().color, timer.0.remaining().as_millis() as u64), &pool, &mut server);
+            continue;
+        }
+        if !room.members.contains(&client_id) || !timer.0.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let color = ludo_match.active().color;
+        let counted = ludo_match.active_mut().record_timeout();
+        let timeouts = ludo_match.active().timeouts;
+        info!("turn of {:?} in room {} timed out ({} in a row).", color, room.id, timeouts);
+        match ludo_match.phase.clone() {
+            LudoTurnPhase::AwaitingRoll => game::roll_active(room, &mut ludo_match, &mut dice, &pool, &mut server),
+            LudoTurnPhase::AwaitingMove(_, moves) => {
+                /* the same choice a greedy bot would make, so a timeout never throws the match. */
+                if let Some(chosen) = bot::choose_move(&ludo_match.board, &moves, LudoBotDifficulty::Greedy) {
+                    if let Err(error) = ludo_match.play(chosen.token) {
+                        warn!("automatic move of {:?} failed: {}", color, error);
+                        ludo_match.pass();
+                    }
+                } else {
+                    ludo_match.pass();
+                }
+                ludo_match.broadcast(room, &pool, &mut server);
+            }
+        }
+        if counted && configuration.turn_timeouts_to_kick > 0 && timeouts >= configuration.turn_timeouts_to_kick {
+            if let Some(seat) = ludo_match.seats.iter_mut().find(|seat| seat.color == color) {
+                /* without a profile the seat is not reserved for a reconnect. */
+                seat.profile = None;
+                if configuration.bot_replace_dropped {
+                    seat.occupant = LudoSeatOccupant::Bot(room.bots.unwrap_or(configuration.bot_difficulty));
+                }
+            }
+            pool.send_packet(client_id, LudoGameOutcomeDisconnectPacket::new("too many turn timeouts!".to_string()), &mut server);
+            server.disconnect(client_id);
+            warn!("client {} was kicked from room {} after {} turn timeouts.", client_id, room.id, timeouts);
+        }
+    }
+}
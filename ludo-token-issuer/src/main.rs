@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+use anyhow::{anyhow, Result};
+use bevy_renet::netcode::ConnectToken;
+use clap::{Parser, Subcommand};
+use ludo_commons::game::LudoGameProfile;
+use ludo_commons::{protocol, security};
+
+/// Mints netcode connect tokens for the ludo server, run next to the server with access to its private key.
+#[derive(Parser)]
+#[command(name = "ludo-token-issuer", version)]
+struct LudoTokenIssuer {
+    #[command(subcommand)]
+    command: LudoTokenIssuerCommand,
+}
+
+#[derive(Subcommand)]
+enum LudoTokenIssuerCommand {
+    /// Generate a new private key for the server.
+    Keygen {
+        #[arg(long, default_value = security::LUDO_PRIVATE_KEY_FILE)]
+        key: String,
+        /// Replace an existing key, every issued token becomes invalid.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Issue a connect token bound to the profile id of a client.
+    Issue {
+        #[arg(long, default_value = security::LUDO_PRIVATE_KEY_FILE)]
+        key: String,
+        /// Game profile of the client, only its unique id is used.
+        #[arg(long, default_value = "game-profile.json")]
+        profile: String,
+        #[arg(long, default_value = "127.0.0.1:2000")]
+        server: Vec<SocketAddr>,
+        #[arg(long, default_value_t = 3600)]
+        expire_seconds: u64,
+        #[arg(long, default_value_t = 15)]
+        timeout_seconds: i32,
+        #[arg(long, default_value = security::LUDO_CONNECT_TOKEN_FILE)]
+        output: String,
+    },
+}
+
+fn main() -> Result<()> {
+    match LudoTokenIssuer::parse().command {
+        LudoTokenIssuerCommand::Keygen { key, force } => {
+            if !force && std::path::Path::new(&key).exists() {
+                return Err(anyhow!("{} already exists, use --force to replace it", key));
+            }
+            security::write_key(&key, &security::generate_key())?;
+            println!("private key written to {}", key);
+        }
+        LudoTokenIssuerCommand::Issue { key, profile, server, expire_seconds, timeout_seconds, output } => {
+            let private_key = security::load_key(&key)?;
+            let profile = LudoGameProfile::read_from_file(&profile)?;
+            let user_data = security::profile_user_data(&profile.unique_id);
+            let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+            let client_id = rand::random::<u64>();
+            let connect_token = ConnectToken::generate(current_time, protocol::LUDO_PROTOCOL_ID, expire_seconds, client_id, timeout_seconds, server, Some(&user_data), &private_key)?;
+            connect_token.write(&mut File::create(&output)?)?;
+            println!("connect token for {} ({}) written to {}", profile.nickname, client_id, output);
+        }
+    }
+    Ok(())
+}